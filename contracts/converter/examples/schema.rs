@@ -5,9 +5,11 @@
 use std::env::current_dir;
 use std::fs::create_dir_all;
 
-use astroport::pair::{Cw20HookMsg, ExecuteMsg, QueryMsg};
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
-use lido_terra_stluna_bluna_converter_contract::msgs::InstantiateMsg;
+use lido_terra_stluna_bluna_converter_contract::msgs::{
+    ConversionQuoteResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, PricingModelResponse,
+    QueryMsg, RateHistoryResponse, TwapResponse,
+};
 use lido_terra_stluna_bluna_converter_contract::state::ConfigResponse;
 
 fn main() {
@@ -21,4 +23,8 @@ fn main() {
     export_schema(&schema_for!(Cw20HookMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(ConversionQuoteResponse), &out_dir);
+    export_schema(&schema_for!(PricingModelResponse), &out_dir);
+    export_schema(&schema_for!(TwapResponse), &out_dir);
+    export_schema(&schema_for!(RateHistoryResponse), &out_dir);
 }
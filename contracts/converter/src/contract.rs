@@ -3,28 +3,80 @@
 // Copyright Lido
 
 use crate::error::ContractError;
-use crate::state::{Config, ConfigResponse, CONFIG, SWAP_REQUEST};
+use crate::migration::{migrate_from_v100, parse_version, VERSION_1_0_0};
+use crate::state::{
+    AccumulatorMode, Config, ConfigResponse, NativeSwapMode, Observation, OwnershipProposal,
+    PendingSwap, RateSnapshot, ReserveModel, CONFIG, NEXT_SWAP_REPLY_ID, OBSERVATIONS_COUNT,
+    OWNERSHIP_PROPOSAL, PENDING_SWAPS, RATE_HISTORY,
+};
 
 use cosmwasm_std::{
-    entry_point, from_binary, to_binary, Addr, Binary, CosmosMsg, Decimal, Deps, DepsMut, Env,
-    MessageInfo, Reply, ReplyOn, Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+    attr, entry_point, from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal,
+    Deps, DepsMut, Env, Event, MessageInfo, Order, Reply, ReplyOn, Response, StdError, StdResult,
+    Storage, SubMsg, Uint128, WasmMsg,
 };
 
-use crate::msgs::InstantiateMsg;
-use crate::queries::{query_cw20_balance, query_total_tokens_issued};
+use crate::math::{decimal_division, decimal_division_in_256, decimal_multiplication_in_256};
+use crate::msgs::{
+    ConversionQuoteResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, PricingModelResponse,
+    QueryMsg, RateHistoryResponse, TwapResponse, UpdateConfigParams,
+};
+use crate::oracle::{store_observation, twap_prices, OBSERVATIONS_CAPACITY, OBSERVATIONS_COVERAGE};
+use crate::queries::{query_cw20_balance, query_hub_snapshot, query_total_tokens_issued};
 use crate::simulation::{
-    convert_bluna_to_stluna, convert_stluna_to_bluna, get_required_bluna, get_required_stluna,
+    apply_converter_fee, get_required_bluna, get_required_return_before_fee, get_required_stluna,
+    simulate_bluna_to_stluna, simulate_luna_to_bluna, simulate_luna_to_stluna,
+    simulate_stluna_to_bluna, ConversionSimulation, HubSnapshot,
 };
 use astroport::asset::{addr_validate_to_lower, Asset, AssetInfo, PairInfo};
 use astroport::pair::{
-    CumulativePricesResponse, Cw20HookMsg, ExecuteMsg, MigrateMsg, PoolResponse, QueryMsg,
-    ReverseSimulationResponse, SimulationResponse, TWAP_PRECISION,
+    CumulativePricesResponse, MigrateMsg, PoolResponse, ReverseSimulationResponse,
+    SimulationResponse, DEFAULT_SLIPPAGE, MAX_ALLOWED_SLIPPAGE, TWAP_PRECISION,
 };
-use basset::hub::Cw20HookMsg as HubCw20HookMsg;
+use basset::hub::{Cw20HookMsg as HubCw20HookMsg, ExecuteMsg as HubExecuteMsg};
+use cw2::{set_contract_version, CONTRACT};
 use cw20::Cw20ReceiveMsg;
+use cw_storage_plus::{Bound, U64Key};
+use std::str::FromStr;
 use std::vec;
 
-const SWAP_REPLY_ID: u64 = 1;
+/// Contract name that is used for migration.
+const CONTRACT_NAME: &str = "lido-terra-stluna-bluna-converter";
+/// Contract version that is used for migration.
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const FIRST_SWAP_REPLY_ID: u64 = 1;
+
+/// the denom of native LUNA which can be bonded via the Hub
+const LUNA_DENOM: &str = "uluna";
+
+/// the maximum validity period of an ownership proposal in seconds (14 days)
+const MAX_PROPOSAL_TTL: u64 = 1209600;
+
+/// ## Description
+/// Returns the hard cap of the converter fee rate (1%)
+fn max_fee_rate() -> Decimal {
+    Decimal::percent(1)
+}
+
+/// the custom pair type advertised to routers
+const PAIR_TYPE: &str = "lido-converter";
+
+/// the description of the pricing model returned by the pricing model query
+const PRICING_MODEL_DESCRIPTION: &str = "Converts stLuna and bLuna via the Lido Hub at the ratio of their exchange rates, \
+without slippage. The Hub charges the peg recovery fee from the converted bLuna if the bLuna exchange rate \
+is below the threshold, and the converter charges its fee from the converted tokens. \
+Use the simulation queries to get the exact return amount.";
+
+/// the number of blocks the exchange rate snapshots are kept for (about a day)
+pub const RATE_HISTORY_RETENTION: u64 = 14_400;
+
+/// the maximum number of expired snapshots pruned per update, so the gas cost of an update is bounded
+pub const RATE_HISTORY_PRUNE_LIMIT: u64 = 10;
+
+// Settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
 
 /// ## Description
 /// Creates a new contract with the specified parameters in the [`InstantiateMsg`].
@@ -43,6 +95,8 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
     let config = Config {
         stluna_addr: addr_validate_to_lower(deps.api, msg.stluna_address.as_str())?,
         bluna_addr: addr_validate_to_lower(deps.api, msg.bluna_address.as_str())?,
@@ -51,6 +105,21 @@ pub fn instantiate(
         block_time_last: 0,
         price0_cumulative_last: Uint128::zero(),
         price1_cumulative_last: Uint128::zero(),
+        price0_last: Uint128::zero(),
+        price1_last: Uint128::zero(),
+        accumulator_mode: AccumulatorMode::BlockTime,
+        native_swap_mode: NativeSwapMode::Disabled,
+        fee_rate: Decimal::zero(),
+        fee_collector: None,
+        guardian: None,
+        paused: false,
+        min_price_update_interval: 0,
+        rate_jump_threshold: Decimal::zero(),
+        circuit_breaker_threshold: Decimal::zero(),
+        circuit_breaker_window: 0,
+        circuit_breaker_tripped: false,
+        circuit_breaker_reset_time: 0,
+        reserve_model: ReserveModel::ExchangeRate,
     };
 
     CONFIG.save(deps.storage, &config)?;
@@ -70,7 +139,43 @@ pub fn instantiate(
 /// * **msg** is the object of type [`ExecuteMsg`].
 ///
 /// ## Queries
-/// * **ExecuteMsg::UpdateConfig { params: Binary }** Not supported.
+/// * **ExecuteMsg::UpdateConfig {
+///             hub_address,
+///             stluna_address,
+///             bluna_address,
+///             native_swap_mode,
+///             fee_rate,
+///             fee_collector,
+///             guardian,
+///             min_price_update_interval,
+///             accumulator_mode,
+///             rate_jump_threshold,
+///             circuit_breaker_threshold,
+///             circuit_breaker_window,
+///             reserve_model,
+///         }** Updates the Lido contract addresses, the native swap mode, the converter fee, the guardian,
+/// the price update interval, the accumulator mode, the rate jump threshold, the circuit breaker settings
+/// and the reserve model.
+///
+/// * **ExecuteMsg::UpdatePrices {}** Accumulates the cumulative prices up to the current block.
+///
+/// * **ExecuteMsg::Pause {}** Pauses swaps.
+///
+/// * **ExecuteMsg::Unpause {}** Unpauses swaps.
+///
+/// * **ExecuteMsg::ResetCircuitBreaker {}** Resets the tripped circuit breaker.
+///
+/// * **ExecuteMsg::ProposeNewOwner { owner, expires_in }** Creates a request to change ownership.
+///
+/// * **ExecuteMsg::DropOwnershipProposal {}** Removes a request to change ownership.
+///
+/// * **ExecuteMsg::ClaimOwnership {}** Approves ownership.
+///
+/// * **ExecuteMsg::Sweep {
+///             asset_info,
+///             recipient,
+///             amount,
+///         }** Sends tokens stuck on the contract balance to the recipient.
 ///
 /// * **ExecuteMsg::Receive(msg)** Receives a message of type [`Cw20ReceiveMsg`] and processes
 /// it depending on the received template.
@@ -87,7 +192,8 @@ pub fn instantiate(
 ///             belief_price,
 ///             max_spread,
 ///             to,
-///         }** Performs an swap operation with the specified parameters.
+///         }** Performs an swap operation with the specified parameters using the CW20 allowance of the sender
+/// or the native LUNA sent with the message.
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -96,7 +202,53 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::UpdateConfig { .. } => Err(ContractError::NonSupported {}),
+        ExecuteMsg::UpdateConfig {
+            hub_address,
+            stluna_address,
+            bluna_address,
+            native_swap_mode,
+            fee_rate,
+            fee_collector,
+            guardian,
+            min_price_update_interval,
+            accumulator_mode,
+            rate_jump_threshold,
+            circuit_breaker_threshold,
+            circuit_breaker_window,
+            reserve_model,
+        } => update_config(
+            deps,
+            info,
+            UpdateConfigParams {
+                hub_address,
+                stluna_address,
+                bluna_address,
+                native_swap_mode,
+                fee_rate,
+                fee_collector,
+                guardian,
+                min_price_update_interval,
+                accumulator_mode,
+                rate_jump_threshold,
+                circuit_breaker_threshold,
+                circuit_breaker_window,
+                reserve_model,
+            },
+        ),
+        ExecuteMsg::UpdatePrices {} => update_prices(deps, env),
+        ExecuteMsg::Pause {} => set_paused(deps, info, true),
+        ExecuteMsg::Unpause {} => set_paused(deps, info, false),
+        ExecuteMsg::ResetCircuitBreaker {} => reset_circuit_breaker(deps, env, info),
+        ExecuteMsg::ProposeNewOwner { owner, expires_in } => {
+            propose_new_owner(deps, env, info, owner, expires_in)
+        }
+        ExecuteMsg::DropOwnershipProposal {} => drop_ownership_proposal(deps, info),
+        ExecuteMsg::ClaimOwnership {} => claim_ownership(deps, env, info),
+        ExecuteMsg::Sweep {
+            asset_info,
+            recipient,
+            amount,
+        } => sweep(deps, env, info, asset_info, recipient, amount),
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::ProvideLiquidity {
             assets: _,
@@ -105,12 +257,567 @@ pub fn execute(
             receiver: _,
         } => Err(ContractError::NonSupported {}),
         ExecuteMsg::Swap {
-            offer_asset: _,
-            belief_price: _,
-            max_spread: _,
-            to: _,
-        } => Err(ContractError::NonSupported {}),
+            offer_asset,
+            belief_price,
+            max_spread,
+            to,
+        } => execute_swap(deps, env, info, offer_asset, belief_price, max_spread, to),
+    }
+}
+
+/// ## Description
+/// Updates the Lido contract addresses, the native swap mode, the converter fee, the guardian, the price update interval,
+/// the accumulator mode, the rate jump threshold, the circuit breaker settings and the reserve model. Returns an [`ContractError`] on failure or the [`Response`]
+/// with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only owner can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+///
+/// * **params** is the object of type [`UpdateConfigParams`]. The fee rate is capped by [`max_fee_rate`].
+pub fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    params: UpdateConfigParams,
+) -> Result<Response, ContractError> {
+    let UpdateConfigParams {
+        hub_address,
+        stluna_address,
+        bluna_address,
+        native_swap_mode,
+        fee_rate,
+        fee_collector,
+        guardian,
+        min_price_update_interval,
+        accumulator_mode,
+        rate_jump_threshold,
+        circuit_breaker_threshold,
+        circuit_breaker_window,
+        reserve_model,
+    } = params;
+
+    let mut config: Config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut attributes = vec![attr("action", "update_config")];
+
+    if let Some(hub_address) = hub_address {
+        config.hub_addr = addr_validate_to_lower(deps.api, hub_address.as_str())?;
+        attributes.push(attr("hub_address", config.hub_addr.to_string()));
+    }
+
+    if let Some(stluna_address) = stluna_address {
+        config.stluna_addr = addr_validate_to_lower(deps.api, stluna_address.as_str())?;
+        attributes.push(attr("stluna_address", config.stluna_addr.to_string()));
+    }
+
+    if let Some(bluna_address) = bluna_address {
+        config.bluna_addr = addr_validate_to_lower(deps.api, bluna_address.as_str())?;
+        attributes.push(attr("bluna_address", config.bluna_addr.to_string()));
+    }
+
+    if let Some(native_swap_mode) = native_swap_mode {
+        attributes.push(attr("native_swap_mode", native_swap_mode.to_string()));
+        config.native_swap_mode = native_swap_mode;
+    }
+
+    if let Some(fee_rate) = fee_rate {
+        let max_fee_rate = max_fee_rate();
+        if fee_rate > max_fee_rate {
+            return Err(ContractError::FeeRateTooHigh { max_fee_rate });
+        }
+        config.fee_rate = fee_rate;
+        attributes.push(attr("fee_rate", fee_rate.to_string()));
+    }
+
+    if let Some(fee_collector) = fee_collector {
+        let fee_collector = addr_validate_to_lower(deps.api, fee_collector.as_str())?;
+        attributes.push(attr("fee_collector", fee_collector.to_string()));
+        config.fee_collector = Some(fee_collector);
+    }
+
+    if !config.fee_rate.is_zero() && config.fee_collector.is_none() {
+        return Err(ContractError::FeeCollectorNotSet {});
+    }
+
+    if let Some(guardian) = guardian {
+        let guardian = addr_validate_to_lower(deps.api, guardian.as_str())?;
+        attributes.push(attr("guardian", guardian.to_string()));
+        config.guardian = Some(guardian);
+    }
+
+    if let Some(min_price_update_interval) = min_price_update_interval {
+        config.min_price_update_interval = min_price_update_interval;
+        attributes.push(attr(
+            "min_price_update_interval",
+            min_price_update_interval.to_string(),
+        ));
+    }
+
+    if let Some(accumulator_mode) = accumulator_mode {
+        attributes.push(attr("accumulator_mode", accumulator_mode.to_string()));
+        config.accumulator_mode = accumulator_mode;
+    }
+
+    if let Some(rate_jump_threshold) = rate_jump_threshold {
+        config.rate_jump_threshold = rate_jump_threshold;
+        attributes.push(attr("rate_jump_threshold", rate_jump_threshold.to_string()));
+    }
+
+    if let Some(circuit_breaker_threshold) = circuit_breaker_threshold {
+        config.circuit_breaker_threshold = circuit_breaker_threshold;
+        attributes.push(attr(
+            "circuit_breaker_threshold",
+            circuit_breaker_threshold.to_string(),
+        ));
+    }
+
+    if let Some(circuit_breaker_window) = circuit_breaker_window {
+        config.circuit_breaker_window = circuit_breaker_window;
+        attributes.push(attr(
+            "circuit_breaker_window",
+            circuit_breaker_window.to_string(),
+        ));
     }
+
+    if !config.circuit_breaker_threshold.is_zero() && config.circuit_breaker_window == 0 {
+        return Err(ContractError::CircuitBreakerWindowNotSet {});
+    }
+
+    if config.circuit_breaker_window > OBSERVATIONS_COVERAGE {
+        return Err(ContractError::CircuitBreakerWindowTooLong {
+            max_window: OBSERVATIONS_COVERAGE,
+        });
+    }
+
+    if let Some(reserve_model) = reserve_model {
+        attributes.push(attr("reserve_model", reserve_model.to_string()));
+        config.reserve_model = reserve_model;
+    }
+
+    if config.stluna_addr == config.bluna_addr {
+        return Err(ContractError::DoublingAssets {});
+    }
+
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(attributes))
+}
+
+/// ## Description
+/// Accumulates the cumulative prices up to the current block, so the prices advance without swaps.
+/// Returns an [`ContractError`] if the minimum interval since the last update has not passed.
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **env** is the object of type [`Env`].
+pub fn update_prices(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut config: Config = CONFIG.load(deps.storage)?;
+
+    let next_update_time = config
+        .block_time_last
+        .saturating_add(config.min_price_update_interval);
+    if env.block.time.seconds() < next_update_time {
+        return Err(ContractError::PriceUpdateTooEarly { next_update_time });
+    }
+
+    let hub = query_hub_snapshot(
+        deps.as_ref(),
+        config.hub_addr.clone(),
+        config.bluna_addr.clone(),
+    )?;
+
+    // the circuit breaker stays tripped after the deviation until the guardian resets it
+    let mut circuit_breaker_tripped = false;
+    if !config.circuit_breaker_tripped && is_price_deviated(deps.storage, &config, &hub)? {
+        config.circuit_breaker_tripped = true;
+        circuit_breaker_tripped = true;
+        CONFIG.save(deps.storage, &config)?;
+    }
+
+    let rate_jump = save_accumulated_prices(deps.storage, env, &mut config, &hub)?;
+
+    let mut attributes = vec![
+        attr("action", "update_prices"),
+        attr("price0_cumulative_last", config.price0_cumulative_last),
+        attr("price1_cumulative_last", config.price1_cumulative_last),
+        attr("block_time_last", config.block_time_last.to_string()),
+    ];
+    if circuit_breaker_tripped {
+        attributes.push(attr("circuit_breaker_tripped", "true"));
+    }
+
+    Ok(Response::new()
+        .add_events(rate_jump)
+        .add_attributes(attributes))
+}
+
+/// ## Description
+/// Resets the tripped circuit breaker. The circuit breaker does not compare the Hub's prices with the TWAP
+/// until the whole TWAP window passes after the reset.
+/// Returns an [`ContractError`] on failure or the [`Response`] with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only guardian can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **env** is the object of type [`Env`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+pub fn reset_circuit_breaker(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut config: Config = CONFIG.load(deps.storage)?;
+
+    if Some(&info.sender) != config.guardian.as_ref() {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.circuit_breaker_tripped = false;
+    config.circuit_breaker_reset_time = env.block.time.seconds();
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", "reset_circuit_breaker"))
+}
+
+/// ## Description
+/// Pauses or unpauses swaps. Queries keep working while swaps are paused.
+/// Returns an [`ContractError`] on failure or the [`Response`] with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only owner or guardian can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+///
+/// * **paused** is the object of type [`bool`].
+pub fn set_paused(
+    deps: DepsMut,
+    info: MessageInfo,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    let mut config: Config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner && Some(&info.sender) != config.guardian.as_ref() {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.paused = paused;
+    CONFIG.save(deps.storage, &config)?;
+
+    let action = if paused { "pause" } else { "unpause" };
+    Ok(Response::new().add_attribute("action", action))
+}
+
+/// ## Description
+/// Creates a new request to change ownership. Returns an [`ContractError`] on failure or the [`Response`]
+/// with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only owner can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **env** is the object of type [`Env`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+///
+/// * **owner** is the object of type [`String`]. The proposed owner.
+///
+/// * **expires_in** is the validity period of the proposal in seconds. Must not exceed [`MAX_PROPOSAL_TTL`].
+pub fn propose_new_owner(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    expires_in: u64,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let new_owner = addr_validate_to_lower(deps.api, owner.as_str())?;
+    if new_owner == config.owner {
+        return Err(ContractError::NewOwnerSameAsCurrent {});
+    }
+
+    if expires_in > MAX_PROPOSAL_TTL {
+        return Err(ContractError::OwnershipProposalTtlTooLong {
+            max_ttl: MAX_PROPOSAL_TTL,
+        });
+    }
+
+    OWNERSHIP_PROPOSAL.save(
+        deps.storage,
+        &OwnershipProposal {
+            owner: new_owner.clone(),
+            ttl: env.block.time.seconds() + expires_in,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_new_owner"),
+        attr("new_owner", new_owner),
+    ]))
+}
+
+/// ## Description
+/// Removes a request to change ownership. Returns an [`ContractError`] on failure or the [`Response`]
+/// with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only owner can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+pub fn drop_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("action", "drop_ownership_proposal"))
+}
+
+/// ## Description
+/// Approves the new owner proposed by the current owner. Returns an [`ContractError`] on failure or the [`Response`]
+/// with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only the proposed owner can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **env** is the object of type [`Env`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+pub fn claim_ownership(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let proposal = OWNERSHIP_PROPOSAL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::OwnershipProposalNotFound {})?;
+
+    if info.sender != proposal.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if env.block.time.seconds() > proposal.ttl {
+        return Err(ContractError::OwnershipProposalExpired {});
+    }
+
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    CONFIG.update(deps.storage, |mut config| -> StdResult<_> {
+        config.owner = proposal.owner.clone();
+        Ok(config)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "claim_ownership"),
+        attr("new_owner", proposal.owner),
+    ]))
+}
+
+/// ## Description
+/// Sends tokens stuck on the contract balance (e.g. transferred directly, not via `Receive`) to the recipient.
+/// Returns an [`ContractError`] on failure or the [`Response`] with the specified attributes if the operation was successful.
+/// ## Executor
+/// Only owner can execute it
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **env** is the object of type [`Env`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+///
+/// * **asset_info** is the object of type [`AssetInfo`]. The asset to be sent.
+///
+/// * **recipient** is the object of type [`String`]. The recipient of the tokens.
+///
+/// * **amount** is the object of type [`Option<Uint128>`]. The amount of tokens, the whole balance is sent if not set.
+pub fn sweep(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    recipient: String,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let recipient = addr_validate_to_lower(deps.api, recipient.as_str())?;
+
+    let (msg, amount) = match &asset_info {
+        AssetInfo::Token { contract_addr } => {
+            // a pending swap is created and replied within the same transaction, and the reply forwards
+            // only the balance delta of the ask token, so the swept balance never includes swap funds
+            let amount = match amount {
+                Some(amount) => amount,
+                None => {
+                    query_cw20_balance(deps.as_ref(), contract_addr.clone(), env.contract.address)?
+                }
+            };
+
+            let msg = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            });
+            (msg, amount)
+        }
+        AssetInfo::NativeToken { denom } => {
+            let amount = match amount {
+                Some(amount) => amount,
+                None => {
+                    deps.querier
+                        .query_balance(env.contract.address, denom.as_str())?
+                        .amount
+                }
+            };
+
+            let msg = CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount,
+                }],
+            });
+            (msg, amount)
+        }
+    };
+
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    Ok(Response::new().add_message(msg).add_attributes(vec![
+        attr("action", "sweep"),
+        attr("asset", asset_info.to_string()),
+        attr("recipient", recipient),
+        attr("amount", amount),
+    ]))
+}
+
+/// ## Description
+/// Performs an swap operation with the specified parameters using the CW20 allowance of the sender.
+/// The offer tokens are pulled from the sender with [`cw20::Cw20ExecuteMsg::TransferFrom`] before the conversion.
+/// Native LUNA has to be sent with the message, it is bonded via the Hub.
+/// Returns an [`ContractError`] on failure, otherwise returns the [`Response`] with the specified attributes if the operation was successful.
+/// ## Params
+/// * **deps** is the object of type [`DepsMut`].
+///
+/// * **env** is the object of type [`Env`].
+///
+/// * **info** is the object of type [`MessageInfo`].
+///
+/// * **offer_asset** is the object of type [`Asset`]. Proposed asset for swapping.
+///
+/// * **belief_price** is the object of type [`Option<Decimal>`]. Used to calculate the maximum spread.
+///
+/// * **max_spread** is the object of type [`Option<Decimal>`]. Sets the maximum spread of the swap operation.
+///
+/// * **to** is the object of type [`Option<String>`]. Sets the recipient of the swap operation.
+pub fn execute_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    offer_asset: Asset,
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
+    to: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+
+    let to_addr = if let Some(to_addr) = to {
+        Some(addr_validate_to_lower(deps.api, to_addr.as_str())?)
+    } else {
+        None
+    };
+
+    let token_addr = match &offer_asset.info {
+        AssetInfo::Token { contract_addr } => {
+            // the offer tokens are pulled with an allowance, so any sent coins would get stuck
+            if !info.funds.is_empty() {
+                return Err(ContractError::UnexpectedFunds {});
+            }
+            contract_addr.clone()
+        }
+        AssetInfo::NativeToken { denom } => {
+            if info.funds.iter().any(|coin| coin.denom != *denom) {
+                return Err(ContractError::UnexpectedFunds {});
+            }
+            offer_asset.assert_sent_native_token_balance(&info)?;
+
+            let sender = info.sender.clone();
+            return swap(
+                deps,
+                env,
+                info,
+                config,
+                sender,
+                offer_asset,
+                belief_price,
+                max_spread,
+                to_addr,
+                None,
+            );
+        }
+    };
+
+    if !(config.stluna_addr == token_addr || config.bluna_addr == token_addr) {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    // the offer tokens have to be on the contract balance before the conversion
+    let transfer_from_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: token_addr.to_string(),
+        msg: to_binary(&cw20::Cw20ExecuteMsg::TransferFrom {
+            owner: info.sender.to_string(),
+            recipient: env.contract.address.to_string(),
+            amount: offer_asset.amount,
+        })?,
+        funds: vec![],
+    });
+
+    let sender = info.sender.clone();
+    let mut response = swap(
+        deps,
+        env,
+        info,
+        config,
+        sender,
+        offer_asset,
+        belief_price,
+        max_spread,
+        to_addr,
+        None,
+    )?;
+    response.messages.insert(0, SubMsg::new(transfer_from_msg));
+
+    Ok(response)
 }
 
 /// ## Description
@@ -131,11 +838,16 @@ pub fn receive_cw20(
     info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
+    if !info.funds.is_empty() {
+        return Err(ContractError::UnexpectedFunds {});
+    }
+
     match from_binary(&cw20_msg.msg) {
         Ok(Cw20HookMsg::Swap {
             belief_price,
             max_spread,
             to,
+            minimum_receive,
         }) => {
             let config: Config = CONFIG.load(deps.storage)?;
 
@@ -163,6 +875,7 @@ pub fn receive_cw20(
                 belief_price,
                 max_spread,
                 to_addr,
+                minimum_receive,
             )
         }
         Ok(Cw20HookMsg::WithdrawLiquidity {}) => Err(ContractError::NonSupported {}),
@@ -172,6 +885,7 @@ pub fn receive_cw20(
 
 /// ## Description
 /// Performs an swap operation with the specified parameters. CONTRACT - a user must do token approval.
+/// The offer tokens are converted by the Hub, the native LUNA is bonded.
 /// Returns an [`ContractError`] on failure, otherwise returns the [`Response`] with the specified attributes if the operation was successful.
 /// ## Params
 /// * **deps** is the object of type [`DepsMut`].
@@ -189,51 +903,125 @@ pub fn receive_cw20(
 /// * **max_spread** is the object of type [`Option<Decimal>`]. Sets the maximum spread of the swap operation.
 ///
 /// * **to** is the object of type [`Option<Addr>`]. Sets the recipient of the swap operation.
+///
+/// * **minimum_receive** is the object of type [`Option<Uint128>`]. Sets the minimum amount of ask tokens the recipient must get.
 #[allow(clippy::too_many_arguments)]
 pub fn swap(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _info: MessageInfo,
     config: Config,
     sender: Addr,
     offer_asset: Asset,
-    _belief_price: Option<Decimal>,
-    _max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
     to: Option<Addr>,
+    minimum_receive: Option<Uint128>,
 ) -> Result<Response, ContractError> {
-    let token_addr = if let AssetInfo::Token { contract_addr } = offer_asset.info {
-        contract_addr
-    } else {
-        return Err(ContractError::NonSupported {});
-    };
+    if config.paused {
+        return Err(ContractError::Paused {});
+    }
 
-    let ask_token_addr = if token_addr == config.bluna_addr {
-        config.stluna_addr
-    } else {
-        config.bluna_addr
-    };
+    let ask_token_addr = get_ask_token_addr(&config, &offer_asset.info)?;
+
+    if offer_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let hub = query_hub_snapshot(
+        deps.as_ref(),
+        config.hub_addr.clone(),
+        config.bluna_addr.clone(),
+    )?;
+
+    // the Hub would reject the conversion deep inside the submessage
+    if hub.paused {
+        return Err(ContractError::HubPaused {});
+    }
+
+    if config.circuit_breaker_tripped || is_price_deviated(deps.storage, &config, &hub)? {
+        return Err(ContractError::CircuitBreakerTripped {});
+    }
+
+    let simulation = simulate_swap(&hub, &config, &offer_asset)?;
+
+    // fail fast if the simulated return amount already violates the user's belief price
+    assert_max_spread(
+        belief_price,
+        max_spread,
+        offer_asset.amount,
+        simulation.return_amount,
+        Uint128::zero(),
+    )?;
+
+    // only the tokens converted by the Hub have to be sent to the recipient,
+    // so we remember the ask token balance to calculate the delta in reply handler
+    let ask_token_balance_before =
+        query_cw20_balance(deps.as_ref(), ask_token_addr.clone(), env.contract.address)?;
+
+    // every conversion gets its own reply id, so several swaps in one transaction
+    // do not overwrite each other's pending swaps
+    let reply_id = NEXT_SWAP_REPLY_ID
+        .may_load(deps.storage)?
+        .unwrap_or(FIRST_SWAP_REPLY_ID);
+    NEXT_SWAP_REPLY_ID.save(deps.storage, &(reply_id + 1))?;
 
     // saving recipient of the swap operation and ask token address to the storage
     // to send swapped tokens to the recipient in reply handler
-    if let Some(to_addr) = to {
-        SWAP_REQUEST.save(deps.storage, &(to_addr, ask_token_addr))?;
-    } else {
-        SWAP_REQUEST.save(deps.storage, &(sender, ask_token_addr))?;
-    }
+    PENDING_SWAPS.save(
+        deps.storage,
+        U64Key::new(reply_id),
+        &PendingSwap {
+            recipient: to.unwrap_or_else(|| sender.clone()),
+            sender,
+            offer_asset_info: offer_asset.info.clone(),
+            ask_token: ask_token_addr.clone(),
+            ask_token_balance_before,
+            offer_amount: offer_asset.amount,
+            fee_free_return: simulation.fee_free_return_amount,
+            peg_fee: simulation.peg_fee,
+            commission_amount: simulation
+                .commission_amount
+                .checked_sub(simulation.converter_fee)?,
+            hub,
+            belief_price,
+            max_spread,
+            minimum_receive,
+        },
+    )?;
 
-    let convert_message = HubCw20HookMsg::Convert {};
-    let msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: token_addr.to_string(),
-        msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
-            contract: config.hub_addr.to_string(),
-            amount: offer_asset.amount,
-            msg: to_binary(&convert_message)?,
-        })?,
-        funds: vec![],
-    });
+    let msg = match offer_asset.info {
+        AssetInfo::Token { contract_addr } => {
+            let convert_message = HubCw20HookMsg::Convert {};
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: config.hub_addr.to_string(),
+                    amount: offer_asset.amount,
+                    msg: to_binary(&convert_message)?,
+                })?,
+                funds: vec![],
+            })
+        }
+        AssetInfo::NativeToken { denom } => {
+            let bond_message = if ask_token_addr == config.bluna_addr {
+                HubExecuteMsg::Bond {}
+            } else {
+                HubExecuteMsg::BondForStLuna {}
+            };
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: config.hub_addr.to_string(),
+                msg: to_binary(&bond_message)?,
+                funds: vec![Coin {
+                    denom,
+                    amount: offer_asset.amount,
+                }],
+            })
+        }
+    };
 
     let sub_msg = SubMsg {
-        id: SWAP_REPLY_ID,
+        id: reply_id,
         msg,
         gas_limit: None,
         reply_on: ReplyOn::Success,
@@ -242,6 +1030,62 @@ pub fn swap(
     Ok(Response::new().add_submessage(sub_msg))
 }
 
+/// ## Description
+/// Returns the address of the token the offer asset is swapped to. Native LUNA is swapped
+/// to the token set by [`NativeSwapMode`], other native tokens are not supported.
+/// ## Params
+/// * **config** is the object of type [`Config`].
+///
+/// * **offer_asset_info** is the object of type [`AssetInfo`].
+fn get_ask_token_addr(
+    config: &Config,
+    offer_asset_info: &AssetInfo,
+) -> Result<Addr, ContractError> {
+    match offer_asset_info {
+        AssetInfo::Token { contract_addr } if *contract_addr == config.stluna_addr => {
+            Ok(config.bluna_addr.clone())
+        }
+        AssetInfo::Token { contract_addr } if *contract_addr == config.bluna_addr => {
+            Ok(config.stluna_addr.clone())
+        }
+        AssetInfo::Token { .. } => Err(ContractError::AssetMismatch {}),
+        AssetInfo::NativeToken { denom } if denom == LUNA_DENOM => match config.native_swap_mode {
+            NativeSwapMode::Bluna => Ok(config.bluna_addr.clone()),
+            NativeSwapMode::Stluna => Ok(config.stluna_addr.clone()),
+            NativeSwapMode::Disabled => Err(ContractError::NonSupported {}),
+        },
+        AssetInfo::NativeToken { .. } => Err(ContractError::NonSupported {}),
+    }
+}
+
+/// ## Description
+/// Simulates the swap of the offer asset, which has to be checked by [`get_ask_token_addr`] before.
+/// The converter fee is deducted from the return amount.
+/// ## Params
+/// * **hub** is the object of type [`HubSnapshot`].
+///
+/// * **config** is the object of type [`Config`].
+///
+/// * **offer_asset** is the object of type [`Asset`].
+fn simulate_swap(
+    hub: &HubSnapshot,
+    config: &Config,
+    offer_asset: &Asset,
+) -> Result<ConversionSimulation, ContractError> {
+    let simulation = match &offer_asset.info {
+        AssetInfo::Token { contract_addr } if *contract_addr == config.bluna_addr => {
+            simulate_bluna_to_stluna(hub, offer_asset.amount)?
+        }
+        AssetInfo::Token { .. } => simulate_stluna_to_bluna(hub, offer_asset.amount)?,
+        AssetInfo::NativeToken { .. } => match config.native_swap_mode {
+            NativeSwapMode::Stluna => simulate_luna_to_stluna(hub, offer_asset.amount)?,
+            _ => simulate_luna_to_bluna(hub, offer_asset.amount)?,
+        },
+    };
+
+    apply_converter_fee(simulation, config.fee_rate)
+}
+
 /// # Description
 /// The entry point to the contract for processing the reply from the submessage
 /// # Params
@@ -251,36 +1095,96 @@ pub fn swap(
 ///
 /// * **msg** is the object of type [`Reply`].
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, env: Env, _msg: Reply) -> Result<Response, ContractError> {
-    let swap_request = SWAP_REQUEST.load(deps.storage)?;
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let pending_swap = PENDING_SWAPS.load(deps.storage, U64Key::new(msg.id))?;
+    let mut config = CONFIG.load(deps.storage)?;
 
-    let return_amount = query_cw20_balance(
+    let converted_amount = query_cw20_balance(
         deps.as_ref(),
-        swap_request.1.clone(),
+        pending_swap.ask_token.clone(),
         env.contract.address.clone(),
-    )?;
+    )?
+    .checked_sub(pending_swap.ask_token_balance_before)?;
 
-    let mut config = CONFIG.load(deps.storage)?;
+    // the converter fee is charged from the converted tokens
+    let converter_fee = converted_amount * config.fee_rate;
+    let return_amount = converted_amount.checked_sub(converter_fee)?;
 
-    if let Some((price0_cumulative_new, price1_cumulative_new, block_time)) =
-        accumulate_prices(deps.as_ref(), env, &config)?
-    {
-        config.price0_cumulative_last = price0_cumulative_new;
-        config.price1_cumulative_last = price1_cumulative_new;
-        config.block_time_last = block_time;
-        CONFIG.save(deps.storage, &config)?;
+    let commission_amount = pending_swap.commission_amount.checked_add(converter_fee)?;
+
+    // the spread is the shortfall against the fee-free conversion, the same as in the simulations
+    let spread_amount = pending_swap
+        .fee_free_return
+        .saturating_sub(return_amount.checked_add(commission_amount)?);
+    assert_max_spread(
+        pending_swap.belief_price,
+        pending_swap.max_spread,
+        pending_swap.offer_amount,
+        return_amount,
+        spread_amount,
+    )?;
+
+    if let Some(minimum_receive) = pending_swap.minimum_receive {
+        if return_amount < minimum_receive {
+            return Err(ContractError::MinimumReceiveAssertion {
+                minimum_receive,
+                return_amount,
+            });
+        }
     }
 
-    let msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: swap_request.1.to_string(),
+    PENDING_SWAPS.remove(deps.storage, U64Key::new(msg.id));
+
+    // the prices are accumulated with the Hub's state loaded by the swap, so the Hub is queried once per swap
+    let rate_jump = save_accumulated_prices(deps.storage, env, &mut config, &pending_swap.hub)?;
+
+    let mut messages = vec![CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: pending_swap.ask_token.to_string(),
         msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
-            recipient: swap_request.0.to_string(),
+            recipient: pending_swap.recipient.to_string(),
             amount: return_amount,
         })?,
         funds: vec![],
-    });
+    })];
 
-    Ok(Response::new().add_message(msg))
+    if !converter_fee.is_zero() {
+        let fee_collector = config
+            .fee_collector
+            .ok_or(ContractError::FeeCollectorNotSet {})?;
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: pending_swap.ask_token.to_string(),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: fee_collector.to_string(),
+                amount: converter_fee,
+            })?,
+            funds: vec![],
+        }));
+    }
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_events(rate_jump)
+        .add_attributes(vec![
+            attr("action", "swap"),
+            attr("sender", pending_swap.sender),
+            attr("receiver", pending_swap.recipient),
+            attr("offer_asset", pending_swap.offer_asset_info.to_string()),
+            attr("ask_asset", pending_swap.ask_token),
+            attr("offer_amount", pending_swap.offer_amount),
+            attr("return_amount", return_amount),
+            attr("spread_amount", spread_amount),
+            attr("commission_amount", commission_amount),
+            attr("peg_fee_amount", pending_swap.peg_fee),
+            attr("converter_fee_amount", converter_fee),
+            attr(
+                "stluna_exchange_rate",
+                pending_swap.hub.stluna_exchange_rate.to_string(),
+            ),
+            attr(
+                "bluna_exchange_rate",
+                pending_swap.hub.bluna_exchange_rate.to_string(),
+            ),
+        ]))
 }
 
 /// ## Description
@@ -311,6 +1215,18 @@ pub fn reply(deps: DepsMut, env: Env, _msg: Reply) -> Result<Response, ContractE
 ///
 /// * **QueryMsg::Config {}** Returns information about the controls settings in a
 /// [`ConfigResponse`] object.
+///
+/// * **QueryMsg::ConversionQuote { offer_asset }** Returns the breakdown of the conversion in a
+/// [`ConversionQuoteResponse`] object.
+///
+/// * **QueryMsg::Twap { window_seconds }** Returns the time-weighted average prices in a
+/// [`TwapResponse`] object.
+///
+/// * **QueryMsg::RateHistory { start_after, limit }** Returns the snapshots of the Hub's exchange rates
+/// in a [`RateHistoryResponse`] object.
+///
+/// * **QueryMsg::PricingModel {}** Returns the description of the pricing model in a
+/// [`PricingModelResponse`] object.
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
@@ -323,20 +1239,69 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         }
         QueryMsg::CumulativePrices {} => to_binary(&query_cumulative_prices(deps, env)?),
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::ConversionQuote { offer_asset } => {
+            to_binary(&query_conversion_quote(deps, offer_asset)?)
+        }
+        QueryMsg::Twap { window_seconds } => to_binary(&query_twap(deps, env, window_seconds)?),
+        QueryMsg::RateHistory { start_after, limit } => {
+            to_binary(&query_rate_history(deps, start_after, limit)?)
+        }
+        QueryMsg::PricingModel {} => to_binary(&query_pricing_model(deps)?),
     }
 }
 
 /// ## Description
 /// Returns information about a pair in an object of type [`PairInfo`].
+/// The pair advertises the custom pair type, so routers do not price it with the constant product formula.
 /// ## Params
 /// * **deps** is the object of type [`Deps`].
+///
+/// * **env** is the object of type [`Env`].
 pub fn query_pair_info(deps: Deps, env: Env) -> StdResult<PairInfo> {
-    let pool_info = query_pool(deps)?.assets;
+    let config: Config = CONFIG.load(deps.storage)?;
     Ok(PairInfo {
-        asset_infos: [pool_info[0].clone().info, pool_info[1].clone().info],
+        asset_infos: [
+            AssetInfo::Token {
+                contract_addr: config.stluna_addr,
+            },
+            AssetInfo::Token {
+                contract_addr: config.bluna_addr,
+            },
+        ],
         contract_addr: env.contract.address,
         liquidity_token: Addr::unchecked(""),
-        pair_type: astroport::factory::PairType::Xyk {},
+        pair_type: astroport::factory::PairType::Custom(PAIR_TYPE.to_string()),
+    })
+}
+
+/// ## Description
+/// Returns the description of the pricing model with the current Hub's parameters
+/// in a [`PricingModelResponse`] object.
+/// ## Params
+/// * **deps** is the object of type [`Deps`].
+pub fn query_pricing_model(deps: Deps) -> StdResult<PricingModelResponse> {
+    let config: Config = CONFIG.load(deps.storage)?;
+    let hub = query_hub_snapshot(deps, config.hub_addr, config.bluna_addr)?;
+
+    let price = |numerator: Decimal, denominator: Decimal| {
+        if denominator.is_zero() {
+            Decimal::zero()
+        } else {
+            decimal_division_in_256(numerator, denominator)
+        }
+    };
+
+    Ok(PricingModelResponse {
+        model: PAIR_TYPE.to_string(),
+        description: PRICING_MODEL_DESCRIPTION.to_string(),
+        stluna_exchange_rate: hub.stluna_exchange_rate,
+        bluna_exchange_rate: hub.bluna_exchange_rate,
+        stluna_price: price(hub.stluna_exchange_rate, hub.bluna_exchange_rate),
+        bluna_price: price(hub.bluna_exchange_rate, hub.stluna_exchange_rate),
+        er_threshold: hub.er_threshold,
+        peg_recovery_fee: hub.peg_recovery_fee,
+        peg_fee_active: hub.bluna_exchange_rate < hub.er_threshold,
+        fee_rate: config.fee_rate,
     })
 }
 
@@ -346,7 +1311,8 @@ pub fn query_pair_info(deps: Deps, env: Env) -> StdResult<PairInfo> {
 /// * **deps** is the object of type [`Deps`].
 pub fn query_pool(deps: Deps) -> StdResult<PoolResponse> {
     let config: Config = CONFIG.load(deps.storage)?;
-    let (assets, total_share) = pool_info(deps, config)?;
+    let hub = query_hub_snapshot(deps, config.hub_addr.clone(), config.bluna_addr.clone())?;
+    let (assets, total_share) = pool_info(deps, config, &hub)?;
 
     let resp = PoolResponse {
         assets,
@@ -375,29 +1341,23 @@ pub fn query_share(_deps: Deps, _amount: Uint128) -> StdResult<Vec<Asset>> {
 pub fn query_simulation(deps: Deps, offer_asset: Asset) -> StdResult<SimulationResponse> {
     let config: Config = CONFIG.load(deps.storage)?;
 
-    if let AssetInfo::Token { contract_addr } = offer_asset.info {
-        if contract_addr == config.stluna_addr {
-            Ok(SimulationResponse {
-                return_amount: convert_stluna_to_bluna(deps, config, offer_asset.amount)?,
-                spread_amount: Uint128::zero(),
-                commission_amount: Uint128::zero(),
-            })
-        } else if contract_addr == config.bluna_addr {
-            Ok(SimulationResponse {
-                return_amount: convert_bluna_to_stluna(deps, config, offer_asset.amount)?,
-                spread_amount: Uint128::zero(),
-                commission_amount: Uint128::zero(),
-            })
-        } else {
-            Err(StdError::generic_err("invalid offer asset"))
-        }
-    } else {
-        Err(StdError::generic_err("invalid offer asset"))
-    }
+    get_ask_token_addr(&config, &offer_asset.info)
+        .map_err(|_| StdError::generic_err("invalid offer asset"))?;
+
+    let hub = query_hub_snapshot(deps, config.hub_addr.clone(), config.bluna_addr.clone())?;
+    let simulation = simulate_swap(&hub, &config, &offer_asset)?;
+
+    Ok(SimulationResponse {
+        return_amount: simulation.return_amount,
+        spread_amount: simulation.spread_amount,
+        commission_amount: simulation.commission_amount,
+    })
 }
 
 /// ## Description
 /// Returns information about the reverse simulation in a [`ReverseSimulationResponse`] object.
+/// The spread and commission amounts are taken from the forward simulation of the required offer amount.
+/// The required offer amount covers the converter fee as well.
 /// ## Params
 /// * **deps** is the object of type [`Deps`].
 ///
@@ -409,26 +1369,77 @@ pub fn query_reverse_simulation(
     let config: Config = CONFIG.load(deps.storage)?;
 
     if let AssetInfo::Token { contract_addr } = ask_asset.info {
-        if contract_addr == config.stluna_addr {
-            Ok(ReverseSimulationResponse {
-                offer_amount: get_required_bluna(deps, config, ask_asset.amount)?,
-                spread_amount: Uint128::zero(),
-                commission_amount: Uint128::zero(),
-            })
-        } else if contract_addr == config.bluna_addr {
-            Ok(ReverseSimulationResponse {
-                offer_amount: get_required_stluna(deps, config, ask_asset.amount)?,
-                spread_amount: Uint128::zero(),
-                commission_amount: Uint128::zero(),
-            })
-        } else {
-            Err(StdError::generic_err("invalid ask asset"))
+        if !(contract_addr == config.stluna_addr || contract_addr == config.bluna_addr) {
+            return Err(StdError::generic_err("invalid ask asset"));
         }
+
+        let hub = query_hub_snapshot(deps, config.hub_addr.clone(), config.bluna_addr.clone())?;
+        let ask_amount = get_required_return_before_fee(ask_asset.amount, config.fee_rate)?;
+        let (offer_amount, simulation) = if contract_addr == config.stluna_addr {
+            let offer_amount = get_required_bluna(&hub, ask_amount)?;
+            (offer_amount, simulate_bluna_to_stluna(&hub, offer_amount)?)
+        } else {
+            let offer_amount = get_required_stluna(&hub, ask_amount)?;
+            (offer_amount, simulate_stluna_to_bluna(&hub, offer_amount)?)
+        };
+        let simulation = apply_converter_fee(simulation, config.fee_rate)?;
+
+        Ok(ReverseSimulationResponse {
+            offer_amount,
+            spread_amount: simulation.spread_amount,
+            commission_amount: simulation.commission_amount,
+        })
     } else {
         Err(StdError::generic_err("invalid ask asset"))
     }
 }
 
+/// ## Description
+/// Returns the breakdown of the conversion in a [`ConversionQuoteResponse`] object.
+/// ## Params
+/// * **deps** is the object of type [`Deps`].
+///
+/// * **offer_asset** is the object of type [`Asset`].
+pub fn query_conversion_quote(
+    deps: Deps,
+    offer_asset: Asset,
+) -> StdResult<ConversionQuoteResponse> {
+    let config: Config = CONFIG.load(deps.storage)?;
+
+    let ask_token_addr = get_ask_token_addr(&config, &offer_asset.info)
+        .map_err(|_| StdError::generic_err("invalid offer asset"))?;
+
+    let hub = query_hub_snapshot(deps, config.hub_addr.clone(), config.bluna_addr.clone())?;
+    let simulation = simulate_swap(&hub, &config, &offer_asset)?;
+
+    let effective_price = if simulation.return_amount.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(offer_asset.amount, simulation.return_amount)
+    };
+
+    Ok(ConversionQuoteResponse {
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: ask_token_addr,
+            },
+            amount: simulation.return_amount,
+        },
+        offer_asset,
+        stluna_exchange_rate: simulation.stluna_exchange_rate,
+        bluna_exchange_rate: simulation.bluna_exchange_rate,
+        er_threshold: simulation.er_threshold,
+        peg_fee_active: simulation.bluna_exchange_rate < simulation.er_threshold,
+        max_peg_fee: simulation.max_peg_fee,
+        required_peg_fee: simulation.required_peg_fee,
+        peg_fee: simulation.peg_fee,
+        converter_fee: simulation.converter_fee,
+        commission_amount: simulation.commission_amount,
+        spread_amount: simulation.spread_amount,
+        effective_price,
+    })
+}
+
 /// ## Description
 /// Returns information about the cumulative prices in a [`CumulativePricesResponse`] object.
 /// ## Params
@@ -437,16 +1448,15 @@ pub fn query_reverse_simulation(
 /// * **env** is the object of type [`Env`].
 pub fn query_cumulative_prices(deps: Deps, env: Env) -> StdResult<CumulativePricesResponse> {
     let config = CONFIG.load(deps.storage)?;
-    let (assets, total_share) = pool_info(deps, config.clone())?;
+    let hub = query_hub_snapshot(deps, config.hub_addr.clone(), config.bluna_addr.clone())?;
+    let (assets, total_share) = pool_info(deps, config.clone(), &hub)?;
 
     let mut price0_cumulative_last = config.price0_cumulative_last;
     let mut price1_cumulative_last = config.price1_cumulative_last;
 
-    if let Some((price0_cumulative_new, price1_cumulative_new, _)) =
-        accumulate_prices(deps, env, &config)?
-    {
-        price0_cumulative_last = price0_cumulative_new;
-        price1_cumulative_last = price1_cumulative_new;
+    if let Some(accumulated) = accumulate_prices(env, &config, &hub)? {
+        price0_cumulative_last = accumulated.price0_cumulative;
+        price1_cumulative_last = accumulated.price1_cumulative;
     }
 
     let resp = CumulativePricesResponse {
@@ -459,6 +1469,73 @@ pub fn query_cumulative_prices(deps: Deps, env: Env) -> StdResult<CumulativePric
     Ok(resp)
 }
 
+/// ## Description
+/// Returns the time-weighted average prices over the last `window_seconds` in a [`TwapResponse`] object.
+/// The prices are calculated from the current cumulative prices and the ones interpolated from
+/// the stored observations at the beginning of the window.
+/// ## Params
+/// * **deps** is the object of type [`Deps`].
+///
+/// * **env** is the object of type [`Env`].
+///
+/// * **window_seconds** is the length of the averaging window in seconds.
+pub fn query_twap(deps: Deps, env: Env, window_seconds: u64) -> StdResult<TwapResponse> {
+    if window_seconds == 0 {
+        return Err(StdError::generic_err("window must be positive"));
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+
+    let mut latest = Observation {
+        timestamp: config.block_time_last,
+        price0_cumulative: config.price0_cumulative_last,
+        price1_cumulative: config.price1_cumulative_last,
+    };
+    let hub = query_hub_snapshot(deps, config.hub_addr.clone(), config.bluna_addr.clone())?;
+    if let Some(accumulated) = accumulate_prices(env, &config, &hub)? {
+        latest = Observation {
+            timestamp: accumulated.block_time,
+            price0_cumulative: accumulated.price0_cumulative,
+            price1_cumulative: accumulated.price1_cumulative,
+        };
+    }
+
+    let (stluna_price, bluna_price) = twap_prices(deps.storage, latest, window_seconds)?
+        .ok_or(ContractError::TwapWindowNotCovered { window_seconds })?;
+
+    Ok(TwapResponse {
+        window_seconds,
+        stluna_price,
+        bluna_price,
+    })
+}
+
+/// ## Description
+/// Returns the snapshots of the Hub's exchange rates from the newest to the oldest one
+/// in a [`RateHistoryResponse`] object.
+/// ## Params
+/// * **deps** is the object of type [`Deps`].
+///
+/// * **start_after** is the object of type [`Option<u64>`]. The block height to return the older snapshots after.
+///
+/// * **limit** is the object of type [`Option<u32>`]. The maximum number of the snapshots.
+pub fn query_rate_history(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<RateHistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_after.map(Bound::exclusive_int);
+
+    let snapshots = RATE_HISTORY
+        .range(deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|item| item.map(|(_, snapshot)| snapshot))
+        .collect::<StdResult<Vec<RateSnapshot>>>()?;
+
+    Ok(RateHistoryResponse { snapshots })
+}
+
 /// ## Description
 /// Returns information about the controls settings in a [`ConfigResponse`] object.
 /// ## Params
@@ -471,42 +1548,106 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
         bluna_address: config.bluna_addr,
         owner: config.owner,
         block_time_last: config.block_time_last,
+        native_swap_mode: config.native_swap_mode,
+        fee_rate: config.fee_rate,
+        fee_collector: config.fee_collector,
+        guardian: config.guardian,
+        paused: config.paused,
+        min_price_update_interval: config.min_price_update_interval,
+        accumulator_mode: config.accumulator_mode,
+        rate_jump_threshold: config.rate_jump_threshold,
+        circuit_breaker_threshold: config.circuit_breaker_threshold,
+        circuit_breaker_window: config.circuit_breaker_window,
+        circuit_breaker_tripped: config.circuit_breaker_tripped,
+        reserve_model: config.reserve_model,
     })
 }
 
 /// ## Description
-/// Used for migration of contract. Returns the default object of type [`Response`].
+/// Used for migration of contract. Runs the state transformations of every version between the stored
+/// and the new one. Returns an [`ContractError`] on a downgrade or a migration from another contract.
+/// The contract v1.0.0 did not store its version, so it is assumed only if no version is stored.
 /// ## Params
-/// * **_deps** is the object of type [`DepsMut`].
+/// * **deps** is the object of type [`DepsMut`].
 ///
 /// * **_env** is the object of type [`Env`].
 ///
 /// * **_msg** is the object of type [`MigrateMsg`].
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::default())
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored_version = match CONTRACT.may_load(deps.storage)? {
+        Some(contract_version) => {
+            if contract_version.contract != CONTRACT_NAME {
+                return Err(ContractError::InvalidContractName {
+                    contract: contract_version.contract,
+                });
+            }
+            contract_version.version
+        }
+        None => VERSION_1_0_0.to_string(),
+    };
+
+    if parse_version(&stored_version)? > parse_version(CONTRACT_VERSION)? {
+        return Err(ContractError::MigrationDowngrade {
+            stored_version,
+            new_version: CONTRACT_VERSION.to_string(),
+        });
+    }
+
+    if parse_version(&stored_version)? <= parse_version(VERSION_1_0_0)? {
+        migrate_from_v100(deps.storage)?;
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "migrate"),
+        attr("previous_contract_version", stored_version),
+        attr("new_contract_version", CONTRACT_VERSION),
+    ]))
 }
 
 /// ## Description
-/// Returns information about the pool.
+/// Returns information about the pool. The reserves are calculated according to the [`ReserveModel`].
 /// ## Params
 /// * **deps** is the object of type [`Deps`].
 ///
 /// * **config** is the object of type [`Config`].
-pub fn pool_info(deps: Deps, config: Config) -> StdResult<([Asset; 2], Uint128)> {
+///
+/// * **hub** is the object of type [`HubSnapshot`].
+pub fn pool_info(
+    deps: Deps,
+    config: Config,
+    hub: &HubSnapshot,
+) -> StdResult<([Asset; 2], Uint128)> {
+    let (stluna_reserve, bluna_reserve) = match config.reserve_model {
+        ReserveModel::TotalSupply => (
+            query_total_tokens_issued(deps, config.stluna_addr.clone())?,
+            hub.total_bluna_supply,
+        ),
+        ReserveModel::ExchangeRate => {
+            // both reserves are worth the same amount of LUNA,
+            // so the ratio of the reserves is the ratio of the exchange rates
+            (
+                virtual_reserve(hub.total_bond_bluna_amount, hub.stluna_exchange_rate),
+                virtual_reserve(hub.total_bond_bluna_amount, hub.bluna_exchange_rate),
+            )
+        }
+    };
+
     Ok((
         [
             Asset {
                 info: AssetInfo::Token {
-                    contract_addr: config.stluna_addr.clone(),
+                    contract_addr: config.stluna_addr,
                 },
-                amount: query_total_tokens_issued(deps, config.stluna_addr)?,
+                amount: stluna_reserve,
             },
             Asset {
                 info: AssetInfo::Token {
-                    contract_addr: config.bluna_addr.clone(),
+                    contract_addr: config.bluna_addr,
                 },
-                amount: query_total_tokens_issued(deps, config.bluna_addr)?,
+                amount: bluna_reserve,
             },
         ],
         Uint128::zero(),
@@ -514,45 +1655,325 @@ pub fn pool_info(deps: Deps, config: Config) -> StdResult<([Asset; 2], Uint128)>
 }
 
 /// ## Description
-/// Shifts block_time when any price is zero to not fill an accumulator with a new price to that period.
+/// Returns the amount of tokens worth the given amount of LUNA at the exchange rate,
+/// or zero if the exchange rate is zero.
+/// ## Params
+/// * **luna_amount** is the object of type [`Uint128`].
+///
+/// * **exchange_rate** is the object of type [`Decimal`].
+fn virtual_reserve(luna_amount: Uint128, exchange_rate: Decimal) -> Uint128 {
+    if exchange_rate.is_zero() {
+        return Uint128::zero();
+    }
+    decimal_division(luna_amount, exchange_rate)
+}
+
+/// ## Description
+/// Returns a [`ContractError`] on failure.
+/// If `belief_price` and `max_spread` both are given,
+/// we compute new spread else we just use the spread amount passed to the function.
+/// ## Params
+/// * **belief_price** is the object of type [`Option<Decimal>`]. Used to calculate the expected return amount.
+///
+/// * **max_spread** is the object of type [`Option<Decimal>`]. Sets the maximum spread of the swap operation.
+///
+/// * **offer_amount** is the object of type [`Uint128`]. The amount of offered tokens.
+///
+/// * **return_amount** is the object of type [`Uint128`]. The amount of tokens the user gets.
+///
+/// * **spread_amount** is the object of type [`Uint128`]. The difference between the expected and the actual return amounts.
+pub fn assert_max_spread(
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
+    offer_amount: Uint128,
+    return_amount: Uint128,
+    spread_amount: Uint128,
+) -> Result<(), ContractError> {
+    let default_spread = Decimal::from_str(DEFAULT_SLIPPAGE)?;
+    let max_allowed_spread = Decimal::from_str(MAX_ALLOWED_SLIPPAGE)?;
+
+    let max_spread = max_spread.unwrap_or(default_spread);
+    if max_spread > max_allowed_spread {
+        return Err(ContractError::AllowedSpreadAssertion {});
+    }
+
+    if let Some(belief_price) = belief_price {
+        if belief_price.is_zero() {
+            return Err(ContractError::InvalidBeliefPrice {});
+        }
+        let expected_return = decimal_division(offer_amount, belief_price);
+        let spread_amount = expected_return.saturating_sub(return_amount);
+
+        if return_amount < expected_return
+            && Decimal::from_ratio(spread_amount, expected_return) > max_spread
+        {
+            return Err(ContractError::MaxSpreadAssertion {});
+        }
+    } else if !spread_amount.is_zero()
+        && Decimal::from_ratio(spread_amount, return_amount + spread_amount) > max_spread
+    {
+        return Err(ContractError::MaxSpreadAssertion {});
+    }
+
+    Ok(())
+}
+
+/// ## Description
+/// Accumulates the prices up to the current block, saves them to the config and stores
+/// the observation of the cumulative prices and the snapshot of the Hub's exchange rates.
+/// Returns the rate jump event if an exchange rate changed more than the configured threshold
+/// since the previous snapshot.
 /// ## Params
+/// * **storage** is the object of type [`Storage`].
+///
 /// * **env** is the object of type [`Env`].
 ///
 /// * **config** is the object of type [`Config`].
 ///
-/// * **stluna_exchange_rate** is the exchange rate of stLuna token
+/// * **hub** is the object of type [`HubSnapshot`]. The Hub's state the current prices are derived from.
+fn save_accumulated_prices(
+    storage: &mut dyn Storage,
+    env: Env,
+    config: &mut Config,
+    hub: &HubSnapshot,
+) -> StdResult<Option<Event>> {
+    let height = env.block.height;
+    let mut rate_jump = None;
+
+    if let Some(accumulated) = accumulate_prices(env, config, hub)? {
+        config.price0_cumulative_last = accumulated.price0_cumulative;
+        config.price1_cumulative_last = accumulated.price1_cumulative;
+        config.price0_last = accumulated.price0;
+        config.price1_last = accumulated.price1;
+        config.block_time_last = accumulated.block_time;
+        CONFIG.save(storage, config)?;
+        store_observation(
+            storage,
+            &Observation {
+                timestamp: accumulated.block_time,
+                price0_cumulative: accumulated.price0_cumulative,
+                price1_cumulative: accumulated.price1_cumulative,
+            },
+        )?;
+
+        let snapshot = RateSnapshot {
+            height,
+            timestamp: accumulated.block_time,
+            stluna_exchange_rate: accumulated.stluna_exchange_rate,
+            bluna_exchange_rate: accumulated.bluna_exchange_rate,
+        };
+        let previous = RATE_HISTORY
+            .range(storage, None, None, Order::Descending)
+            .next()
+            .transpose()?;
+        if let Some((_, previous)) = previous {
+            if !config.rate_jump_threshold.is_zero()
+                && (exceeds_relative_change(
+                    previous.stluna_exchange_rate,
+                    snapshot.stluna_exchange_rate,
+                    config.rate_jump_threshold,
+                ) || exceeds_relative_change(
+                    previous.bluna_exchange_rate,
+                    snapshot.bluna_exchange_rate,
+                    config.rate_jump_threshold,
+                ))
+            {
+                rate_jump = Some(Event::new("rate_jump").add_attributes(vec![
+                    attr("previous_height", previous.height.to_string()),
+                    attr(
+                        "previous_stluna_exchange_rate",
+                        previous.stluna_exchange_rate.to_string(),
+                    ),
+                    attr(
+                        "previous_bluna_exchange_rate",
+                        previous.bluna_exchange_rate.to_string(),
+                    ),
+                    attr(
+                        "stluna_exchange_rate",
+                        snapshot.stluna_exchange_rate.to_string(),
+                    ),
+                    attr(
+                        "bluna_exchange_rate",
+                        snapshot.bluna_exchange_rate.to_string(),
+                    ),
+                ]));
+            }
+        }
+        RATE_HISTORY.save(storage, U64Key::new(height), &snapshot)?;
+
+        // the oldest snapshots past the retention period are pruned, at most RATE_HISTORY_PRUNE_LIMIT
+        // at a time; at most one snapshot is added per block, so the pruning keeps up with the history
+        if height > RATE_HISTORY_RETENTION {
+            let expired = RATE_HISTORY
+                .range(
+                    storage,
+                    None,
+                    Some(Bound::exclusive_int(height - RATE_HISTORY_RETENTION)),
+                    Order::Ascending,
+                )
+                .take(RATE_HISTORY_PRUNE_LIMIT as usize)
+                .map(|item| item.map(|(_, snapshot)| snapshot.height))
+                .collect::<StdResult<Vec<_>>>()?;
+            for expired_height in expired {
+                RATE_HISTORY.remove(storage, U64Key::new(expired_height));
+            }
+        }
+    }
+
+    Ok(rate_jump)
+}
+
+/// ## Description
+/// Returns true if the value changed more than the threshold relative to the previous one.
+/// ## Params
+/// * **previous** is the object of type [`Decimal`]. The previous value.
+///
+/// * **current** is the object of type [`Decimal`]. The current value.
+///
+/// * **threshold** is the object of type [`Decimal`]. The allowed relative change.
+fn exceeds_relative_change(previous: Decimal, current: Decimal, threshold: Decimal) -> bool {
+    let change = if current > previous {
+        current - previous
+    } else {
+        previous - current
+    };
+    change > decimal_multiplication_in_256(previous, threshold)
+}
+
+/// ## Description
+/// Returns true if the Hub's prices deviate from the TWAP more than the circuit breaker threshold.
+/// The TWAP is calculated from the stored cumulative prices over the circuit breaker window ending
+/// at the last accumulation. The prices are not compared if the circuit breaker is disabled,
+/// the window started before the last reset or before the first stored observation.
+/// The breaker fails closed if the evicted observations are needed to cover the window.
+/// ## Params
+/// * **storage** is the object of type [`Storage`].
+///
+/// * **config** is the object of type [`Config`].
+///
+/// * **hub** is the object of type [`HubSnapshot`].
+fn is_price_deviated(
+    storage: &dyn Storage,
+    config: &Config,
+    hub: &HubSnapshot,
+) -> Result<bool, ContractError> {
+    if config.circuit_breaker_threshold.is_zero()
+        || config.block_time_last
+            < config
+                .circuit_breaker_reset_time
+                .saturating_add(config.circuit_breaker_window)
+    {
+        return Ok(false);
+    }
+
+    let latest = Observation {
+        timestamp: config.block_time_last,
+        price0_cumulative: config.price0_cumulative_last,
+        price1_cumulative: config.price1_cumulative_last,
+    };
+    let (stluna_twap, bluna_twap) =
+        match twap_prices(storage, latest, config.circuit_breaker_window)? {
+            Some(twap) => twap,
+            None => {
+                let count = OBSERVATIONS_COUNT.may_load(storage)?.unwrap_or_default();
+                return Ok(count > OBSERVATIONS_CAPACITY);
+            }
+        };
+
+    let precision = 10u128.pow(TWAP_PRECISION.into());
+    let stluna_price = Decimal::from_ratio(
+        simulate_stluna_to_bluna(hub, Uint128::from(precision))?.return_amount,
+        precision,
+    );
+    let bluna_price = Decimal::from_ratio(
+        simulate_bluna_to_stluna(hub, Uint128::from(precision))?.return_amount,
+        precision,
+    );
+
+    Ok(
+        exceeds_relative_change(stluna_twap, stluna_price, config.circuit_breaker_threshold)
+            || exceeds_relative_change(bluna_twap, bluna_price, config.circuit_breaker_threshold),
+    )
+}
+
+/// ## Description
+/// Describes the prices accumulated up to the current block
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccumulatedPrices {
+    /// the cumulative price of stLuna in bLuna
+    pub price0_cumulative: Uint128,
+    /// the cumulative price of bLuna in stLuna
+    pub price1_cumulative: Uint128,
+    /// the current price of stLuna in bLuna
+    pub price0: Uint128,
+    /// the current price of bLuna in stLuna
+    pub price1: Uint128,
+    /// the Hub's stLuna exchange rate
+    pub stluna_exchange_rate: Decimal,
+    /// the Hub's bLuna exchange rate
+    pub bluna_exchange_rate: Decimal,
+    /// the current block time
+    pub block_time: u64,
+}
+
+/// ## Description
+/// Accumulates the prices since the last time block. In the [`AccumulatorMode::HubIndexModification`] mode
+/// the previous prices are accumulated up to the time the Hub updated its exchange rates, and the current ones after it.
+/// Returns [`None`] if the prices were already accumulated in the current block.
+/// ## Params
+/// * **env** is the object of type [`Env`].
+///
+/// * **config** is the object of type [`Config`].
 ///
-/// * **bluna_exchange_rate** is the exchange rate of bLuna token
+/// * **hub** is the object of type [`HubSnapshot`]. The Hub's state the current prices are derived from.
 pub fn accumulate_prices(
-    deps: Deps,
     env: Env,
     config: &Config,
-) -> StdResult<Option<(Uint128, Uint128, u64)>> {
+    hub: &HubSnapshot,
+) -> StdResult<Option<AccumulatedPrices>> {
     let block_time = env.block.time.seconds();
     if block_time <= config.block_time_last {
         return Ok(None);
     }
 
-    // we have to shift block_time when any price is zero to not fill an accumulator with a new price to that period
+    let stluna_price =
+        simulate_stluna_to_bluna(hub, Uint128::from(10u128.pow(TWAP_PRECISION.into())))?
+            .return_amount;
+    let bluna_price =
+        simulate_bluna_to_stluna(hub, Uint128::from(10u128.pow(TWAP_PRECISION.into())))?
+            .return_amount;
 
-    let time_elapsed = Uint128::from(block_time - config.block_time_last);
+    // the time the current prices are accumulated from
+    let price_changed_at = match config.accumulator_mode {
+        AccumulatorMode::HubIndexModification
+            if hub.last_index_modification > config.block_time_last
+                && hub.last_index_modification < block_time
+                && !config.price0_last.is_zero()
+                && !config.price1_last.is_zero() =>
+        {
+            hub.last_index_modification
+        }
+        _ => config.block_time_last,
+    };
 
-    let stluna_price = convert_stluna_to_bluna(
-        deps,
-        config.clone(),
-        Uint128::from(10u128.pow(TWAP_PRECISION.into())),
-    )?;
-    let bluna_price = convert_bluna_to_stluna(
-        deps,
-        config.clone(),
-        Uint128::from(10u128.pow(TWAP_PRECISION.into())),
-    )?;
+    let previous_elapsed = Uint128::from(price_changed_at - config.block_time_last);
+    let current_elapsed = Uint128::from(block_time - price_changed_at);
 
     let pcl0 = config
         .price0_cumulative_last
-        .wrapping_add(time_elapsed.checked_mul(stluna_price)?);
+        .wrapping_add(previous_elapsed.checked_mul(config.price0_last)?)
+        .wrapping_add(current_elapsed.checked_mul(stluna_price)?);
     let pcl1 = config
         .price1_cumulative_last
-        .wrapping_add(time_elapsed.checked_mul(bluna_price)?);
-    Ok(Some((pcl0, pcl1, block_time)))
+        .wrapping_add(previous_elapsed.checked_mul(config.price1_last)?)
+        .wrapping_add(current_elapsed.checked_mul(bluna_price)?);
+    Ok(Some(AccumulatedPrices {
+        price0_cumulative: pcl0,
+        price1_cumulative: pcl1,
+        price0: stluna_price,
+        price1: bluna_price,
+        stluna_exchange_rate: hub.stluna_exchange_rate,
+        bluna_exchange_rate: hub.bluna_exchange_rate,
+        block_time,
+    }))
 }
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-only
 // Copyright Astroport
 
-use cosmwasm_std::{OverflowError, StdError};
+use cosmwasm_std::{Decimal, OverflowError, StdError, Uint128};
 use thiserror::Error;
 
 /// ## Description
@@ -14,18 +14,84 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("New owner cannot be the same as the current one")]
+    NewOwnerSameAsCurrent {},
+
+    #[error("Ownership proposal not found")]
+    OwnershipProposalNotFound {},
+
+    #[error("Ownership proposal expired")]
+    OwnershipProposalExpired {},
+
+    #[error("Ownership proposal can not be valid for more than {max_ttl} seconds")]
+    OwnershipProposalTtlTooLong { max_ttl: u64 },
+
+    #[error("Fee rate must not exceed {max_fee_rate}")]
+    FeeRateTooHigh { max_fee_rate: Decimal },
+
+    #[error("Fee collector must be set to charge the fee")]
+    FeeCollectorNotSet {},
+
+    #[error("Swaps are paused")]
+    Paused {},
+
+    #[error("The Hub is paused")]
+    HubPaused {},
+
+    #[error("Can not migrate from contract {contract}")]
+    InvalidContractName { contract: String },
+
+    #[error("Invalid contract version {version}")]
+    InvalidContractVersion { version: String },
+
+    #[error("Can not downgrade contract from version {stored_version} to {new_version}")]
+    MigrationDowngrade {
+        stored_version: String,
+        new_version: String,
+    },
+
+    #[error("Prices can not be updated until {next_update_time}")]
+    PriceUpdateTooEarly { next_update_time: u64 },
+
+    #[error("No price observations cover the window of {window_seconds} seconds")]
+    TwapWindowNotCovered { window_seconds: u64 },
+
+    #[error("Circuit breaker is tripped: the Hub's prices deviate from the TWAP")]
+    CircuitBreakerTripped {},
+
+    #[error("Circuit breaker window must be set to enable the circuit breaker")]
+    CircuitBreakerWindowNotSet {},
+
+    #[error("Circuit breaker window can not exceed {max_window} seconds")]
+    CircuitBreakerWindowTooLong { max_window: u64 },
+
+    #[error("Only the offered native coin can be sent with a swap")]
+    UnexpectedFunds {},
+
     #[error("Operation non supported")]
     NonSupported {},
 
     #[error("Event of zero transfer")]
     InvalidZeroAmount {},
 
+    #[error("Conversion cannot be simulated with the current Hub state")]
+    SimulationUnderflow {},
+
     #[error("Operation exceeds max spread limit")]
     MaxSpreadAssertion {},
 
+    #[error("Return amount {return_amount} is less than minimum receive amount {minimum_receive}")]
+    MinimumReceiveAssertion {
+        minimum_receive: Uint128,
+        return_amount: Uint128,
+    },
+
     #[error("Provided spread amount exceeds allowed limit")]
     AllowedSpreadAssertion {},
 
+    #[error("Belief price must be positive")]
+    InvalidBeliefPrice {},
+
     #[error("Operation exceeds max splippage tolerance")]
     MaxSlippageAssertion {},
 
@@ -47,3 +113,12 @@ impl From<OverflowError> for ContractError {
         StdError::from(o).into()
     }
 }
+
+impl From<ContractError> for StdError {
+    fn from(err: ContractError) -> Self {
+        match err {
+            ContractError::Std(err) => err,
+            err => StdError::generic_err(err.to_string()),
+        }
+    }
+}
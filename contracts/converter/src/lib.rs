@@ -3,7 +3,9 @@ pub mod state;
 
 mod error;
 mod math;
+pub mod migration;
 pub mod msgs;
+mod oracle;
 mod queries;
 mod simulation;
 #[cfg(test)]
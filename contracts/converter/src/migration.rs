@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright Lido
+
+use crate::error::ContractError;
+use crate::state::{AccumulatorMode, Config, NativeSwapMode, ReserveModel, CONFIG};
+use cosmwasm_std::{Addr, Decimal, Storage, Uint128};
+use cw_storage_plus::Item;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// ## Description
+/// The version of the contract deployed before the contract version was stored
+pub const VERSION_1_0_0: &str = "1.0.0";
+
+/// ## Description
+/// This structure describes the config of the contract v1.0.0.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigV100 {
+    pub block_time_last: u64,
+    pub price0_cumulative_last: Uint128,
+    pub price1_cumulative_last: Uint128,
+    pub hub_addr: Addr,
+    pub stluna_addr: Addr,
+    pub bluna_addr: Addr,
+    pub owner: Addr,
+}
+
+/// ## Description
+/// Stores the config of the contract v1.0.0
+pub const CONFIG_V100: Item<ConfigV100> = Item::new("config");
+
+/// ## Description
+/// Stores the recipient and the ask token of the last swap of the contract v1.0.0
+pub const SWAP_REQUEST_V100: Item<(Addr, Addr)> = Item::new("swap_recipient");
+
+/// ## Description
+/// Parses the semantic version into its numeric components, so the versions can be compared.
+/// ## Params
+/// * **version** is the object of type [`str`].
+pub fn parse_version(version: &str) -> Result<Vec<u64>, ContractError> {
+    version
+        .split('.')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<u64>, _>>()
+        .map_err(|_| ContractError::InvalidContractVersion {
+            version: version.to_string(),
+        })
+}
+
+/// ## Description
+/// Migrates the state of the contract v1.0.0: the config gets the new fields with their defaults
+/// and the single swap request is replaced by the pending swaps, which are empty between transactions.
+/// ## Params
+/// * **storage** is the object of type [`Storage`].
+pub fn migrate_from_v100(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    let config = CONFIG_V100.load(storage)?;
+
+    CONFIG.save(
+        storage,
+        &Config {
+            block_time_last: config.block_time_last,
+            price0_cumulative_last: config.price0_cumulative_last,
+            price1_cumulative_last: config.price1_cumulative_last,
+            price0_last: Uint128::zero(),
+            price1_last: Uint128::zero(),
+            accumulator_mode: AccumulatorMode::BlockTime,
+            hub_addr: config.hub_addr,
+            stluna_addr: config.stluna_addr,
+            bluna_addr: config.bluna_addr,
+            owner: config.owner,
+            native_swap_mode: NativeSwapMode::Disabled,
+            fee_rate: Decimal::zero(),
+            fee_collector: None,
+            guardian: None,
+            paused: false,
+            min_price_update_interval: 0,
+            rate_jump_threshold: Decimal::zero(),
+            circuit_breaker_threshold: Decimal::zero(),
+            circuit_breaker_window: 0,
+            circuit_breaker_tripped: false,
+            circuit_breaker_reset_time: 0,
+            reserve_model: ReserveModel::ExchangeRate,
+        },
+    )?;
+
+    // the swap request was always processed in the same transaction, so it is stale
+    SWAP_REQUEST_V100.remove(storage);
+
+    Ok(())
+}
@@ -2,6 +2,10 @@
 // Copyright Astroport
 // Copyright Lido
 
+use crate::state::{AccumulatorMode, NativeSwapMode, RateSnapshot, ReserveModel};
+use astroport::asset::{Asset, AssetInfo};
+use cosmwasm_std::{Decimal, Uint128};
+use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -16,3 +20,246 @@ pub struct InstantiateMsg {
     /// the Lido Terra Hub address
     pub hub_address: String,
 }
+
+/// ## Description
+/// This structure describes the execute messages of the contract.
+/// The swap related messages are compatible with Astroport's [`astroport::pair::ExecuteMsg`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Receives a message of type [`Cw20ReceiveMsg`]
+    Receive(Cw20ReceiveMsg),
+    /// Not supported
+    ProvideLiquidity {
+        assets: [Asset; 2],
+        slippage_tolerance: Option<Decimal>,
+        auto_stake: Option<bool>,
+        receiver: Option<String>,
+    },
+    /// Swap an offer asset to the other. Native LUNA is bonded via the Hub
+    /// if it is enabled by [`NativeSwapMode`]
+    Swap {
+        offer_asset: Asset,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
+    },
+    /// Updates the Lido contract addresses, the native swap mode, the converter fee, the guardian,
+    /// the price update interval, the accumulator mode, the rate jump threshold, the circuit breaker settings
+    /// and the reserve model. Only the owner can execute it
+    UpdateConfig {
+        hub_address: Option<String>,
+        stluna_address: Option<String>,
+        bluna_address: Option<String>,
+        native_swap_mode: Option<NativeSwapMode>,
+        /// the share of the converted tokens charged by the converter
+        fee_rate: Option<Decimal>,
+        /// the recipient of the converter fee
+        fee_collector: Option<String>,
+        /// the address which can pause and unpause swaps in addition to the owner
+        guardian: Option<String>,
+        /// the minimum time (in seconds) between the permissionless price updates, 0 to disable the limit
+        min_price_update_interval: Option<u64>,
+        /// the source of the time the accumulated prices changed at
+        accumulator_mode: Option<AccumulatorMode>,
+        /// the relative change of an exchange rate between two snapshots to emit the rate jump event, 0 to disable it
+        rate_jump_threshold: Option<Decimal>,
+        /// the relative deviation of the Hub's prices from the TWAP to halt swaps at, 0 to disable the circuit breaker
+        circuit_breaker_threshold: Option<Decimal>,
+        /// the length (in seconds) of the TWAP window the Hub's prices are compared with
+        circuit_breaker_window: Option<u64>,
+        /// the reserves reported by the pool queries
+        reserve_model: Option<ReserveModel>,
+    },
+    /// Accumulates the cumulative prices up to the current block. Anyone can execute it
+    UpdatePrices {},
+    /// Pauses swaps. Only the owner or the guardian can execute it
+    Pause {},
+    /// Unpauses swaps. Only the owner or the guardian can execute it
+    Unpause {},
+    /// Resets the tripped circuit breaker. Only the guardian can execute it
+    ResetCircuitBreaker {},
+    /// Creates a request to change ownership. Only the owner can execute it
+    ProposeNewOwner {
+        /// the proposed owner
+        owner: String,
+        /// the validity period of the proposal in seconds, at most 14 days
+        expires_in: u64,
+    },
+    /// Removes a request to change ownership. Only the owner can execute it
+    DropOwnershipProposal {},
+    /// Approves ownership. Only the proposed owner can execute it
+    ClaimOwnership {},
+    /// Sends tokens stuck on the contract balance to the recipient. Only the owner can execute it
+    Sweep {
+        asset_info: AssetInfo,
+        recipient: String,
+        /// the whole contract balance is sent if not set
+        amount: Option<Uint128>,
+    },
+}
+
+/// ## Description
+/// This structure describes the parameters of [`ExecuteMsg::UpdateConfig`]. Only the set parameters are updated.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct UpdateConfigParams {
+    /// the Lido Terra Hub address
+    pub hub_address: Option<String>,
+    /// the stLuna token address
+    pub stluna_address: Option<String>,
+    /// the bLuna token address
+    pub bluna_address: Option<String>,
+    /// the token minted for native LUNA
+    pub native_swap_mode: Option<NativeSwapMode>,
+    /// the share of the converted tokens charged by the converter
+    pub fee_rate: Option<Decimal>,
+    /// the recipient of the converter fee
+    pub fee_collector: Option<String>,
+    /// the address which can pause and unpause swaps in addition to the owner
+    pub guardian: Option<String>,
+    /// the minimum time (in seconds) between the permissionless price updates
+    pub min_price_update_interval: Option<u64>,
+    /// the source of the time the accumulated prices changed at
+    pub accumulator_mode: Option<AccumulatorMode>,
+    /// the relative change of an exchange rate between two snapshots to emit the rate jump event
+    pub rate_jump_threshold: Option<Decimal>,
+    /// the relative deviation of the Hub's prices from the TWAP to halt swaps at
+    pub circuit_breaker_threshold: Option<Decimal>,
+    /// the length (in seconds) of the TWAP window the Hub's prices are compared with
+    pub circuit_breaker_window: Option<u64>,
+    /// the reserves reported by the pool queries
+    pub reserve_model: Option<ReserveModel>,
+}
+
+/// ## Description
+/// This structure describes the query messages of the contract.
+/// It is a superset of Astroport's [`astroport::pair::QueryMsg`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Returns information about a pair in an object of type [`astroport::asset::PairInfo`].
+    /// The pair type is [`astroport::factory::PairType::Custom`] with the name of the pricing model
+    Pair {},
+    /// Returns information about a pool in an object of type [`astroport::pair::PoolResponse`]
+    Pool {},
+    /// Returns the controls settings in an object of type [`crate::state::ConfigResponse`]
+    Config {},
+    /// Returns information about the share of the pool in a vector that contains objects of type [`Asset`]
+    Share { amount: Uint128 },
+    /// Returns information about the simulation of the swap in an object of type [`astroport::pair::SimulationResponse`]
+    Simulation { offer_asset: Asset },
+    /// Returns information about the reverse simulation in an object of type [`astroport::pair::ReverseSimulationResponse`]
+    ReverseSimulation { ask_asset: Asset },
+    /// Returns information about the cumulative prices in an object of type [`astroport::pair::CumulativePricesResponse`]
+    CumulativePrices {},
+    /// Returns the breakdown of the conversion in an object of type [`ConversionQuoteResponse`]
+    ConversionQuote { offer_asset: Asset },
+    /// Returns the time-weighted average prices over the last `window_seconds` in an object of type [`TwapResponse`]
+    Twap { window_seconds: u64 },
+    /// Returns the snapshots of the Hub's exchange rates from the newest to the oldest one
+    /// in an object of type [`RateHistoryResponse`]
+    RateHistory {
+        /// the block height to return the older snapshots after
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the description of the pricing model in an object of type [`PricingModelResponse`]
+    PricingModel {},
+}
+
+/// ## Description
+/// This structure describes the pricing model of the converter, so aggregators can route through it
+/// without assuming the constant product formula.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PricingModelResponse {
+    /// the name of the pricing model, the same as the custom pair type
+    pub model: String,
+    /// the description of the pricing model
+    pub description: String,
+    /// the Hub's stLuna exchange rate
+    pub stluna_exchange_rate: Decimal,
+    /// the Hub's bLuna exchange rate
+    pub bluna_exchange_rate: Decimal,
+    /// the fee-free amount of bLuna per one stLuna (`stluna_exchange_rate / bluna_exchange_rate`)
+    pub stluna_price: Decimal,
+    /// the fee-free amount of stLuna per one bLuna (`bluna_exchange_rate / stluna_exchange_rate`)
+    pub bluna_price: Decimal,
+    /// the Hub's threshold of the bLuna exchange rate to apply the peg recovery fee
+    pub er_threshold: Decimal,
+    /// the Hub's peg recovery fee rate
+    pub peg_recovery_fee: Decimal,
+    /// true if the bLuna exchange rate is below the threshold
+    pub peg_fee_active: bool,
+    /// the share of the converted tokens charged by the converter
+    pub fee_rate: Decimal,
+}
+
+/// ## Description
+/// This structure describes the snapshots of the Hub's exchange rates.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RateHistoryResponse {
+    pub snapshots: Vec<RateSnapshot>,
+}
+
+/// ## Description
+/// This structure describes the time-weighted average prices.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapResponse {
+    /// the length of the averaging window in seconds
+    pub window_seconds: u64,
+    /// the average amount of bLuna per one stLuna
+    pub stluna_price: Decimal,
+    /// the average amount of stLuna per one bLuna
+    pub bluna_price: Decimal,
+}
+
+/// ## Description
+/// This structure describes the breakdown of the conversion.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConversionQuoteResponse {
+    /// the offered asset
+    pub offer_asset: Asset,
+    /// the asked asset with the amount the user will get
+    pub ask_asset: Asset,
+    /// the Hub's stLuna exchange rate
+    pub stluna_exchange_rate: Decimal,
+    /// the Hub's bLuna exchange rate
+    pub bluna_exchange_rate: Decimal,
+    /// the Hub's threshold of the bLuna exchange rate to apply the peg recovery fee
+    pub er_threshold: Decimal,
+    /// true if the bLuna exchange rate is below the threshold
+    pub peg_fee_active: bool,
+    /// the maximum peg recovery fee (in bLuna)
+    pub max_peg_fee: Uint128,
+    /// the peg recovery fee required to recover the bLuna peg (in bLuna)
+    pub required_peg_fee: Uint128,
+    /// the peg recovery fee charged by the Hub (in bLuna)
+    pub peg_fee: Uint128,
+    /// the converter fee (in ask tokens)
+    pub converter_fee: Uint128,
+    /// the peg recovery fee in ask tokens plus the converter fee
+    pub commission_amount: Uint128,
+    /// the difference between the fee-free return amount and the return amount with the commission
+    pub spread_amount: Uint128,
+    /// the amount of offered tokens per one asked token
+    pub effective_price: Decimal,
+}
+
+/// ## Description
+/// This structure describes a CW20 hook message.
+/// It is a superset of Astroport's [`astroport::pair::Cw20HookMsg`], so Astroport's hook messages
+/// are handled as well.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    /// Swap a given amount of asset
+    Swap {
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
+        /// the minimum amount of ask tokens the recipient must get
+        minimum_receive: Option<Uint128>,
+    },
+    /// Withdraw liquidity from the pool
+    WithdrawLiquidity {},
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright Lido
+
+use crate::state::{Observation, OBSERVATIONS, OBSERVATIONS_COUNT};
+use astroport::pair::TWAP_PRECISION;
+use cosmwasm_std::{Decimal, StdResult, Storage, Uint128};
+use cw_storage_plus::U64Key;
+
+/// the maximum number of the cumulative price observations kept in the ring buffer
+pub const OBSERVATIONS_CAPACITY: u64 = 256;
+
+/// the minimum time (in seconds) between two stored observations
+pub const OBSERVATION_INTERVAL: u64 = 600;
+
+/// the time (in seconds) the full ring buffer covers regardless of the update frequency (about 42 hours):
+/// all the observations but the newest one are at least [`OBSERVATION_INTERVAL`] apart
+pub const OBSERVATIONS_COVERAGE: u64 = (OBSERVATIONS_CAPACITY - 2) * OBSERVATION_INTERVAL;
+
+/// ## Description
+/// Saves the observation to the ring buffer, overwriting the oldest one when the buffer is full.
+/// A new slot is taken only if the newest observation was made at least [`OBSERVATION_INTERVAL`]
+/// after the previous one, otherwise the newest observation is overwritten in place.
+/// ## Params
+/// * **storage** is the object of type [`Storage`].
+///
+/// * **observation** is the object of type [`Observation`].
+pub fn store_observation(storage: &mut dyn Storage, observation: &Observation) -> StdResult<()> {
+    let count = OBSERVATIONS_COUNT.may_load(storage)?.unwrap_or_default();
+    if count >= 2 {
+        let newest_slot = (count - 1) % OBSERVATIONS_CAPACITY;
+        let newest = OBSERVATIONS.load(storage, U64Key::new(newest_slot))?;
+        let previous =
+            OBSERVATIONS.load(storage, U64Key::new((count - 2) % OBSERVATIONS_CAPACITY))?;
+        if newest.timestamp < previous.timestamp + OBSERVATION_INTERVAL {
+            return OBSERVATIONS.save(storage, U64Key::new(newest_slot), observation);
+        }
+    }
+
+    OBSERVATIONS.save(
+        storage,
+        U64Key::new(count % OBSERVATIONS_CAPACITY),
+        observation,
+    )?;
+    OBSERVATIONS_COUNT.save(storage, &(count + 1))
+}
+
+/// ## Description
+/// Returns the cumulative prices at the given time, linearly interpolated between the stored observations
+/// and the latest accumulator value. Returns [`None`] if the time is older than the oldest observation.
+/// ## Params
+/// * **storage** is the object of type [`Storage`].
+///
+/// * **latest** is the object of type [`Observation`]. The accumulator value at the current time.
+///
+/// * **timestamp** is the time (in seconds) of the requested cumulative prices.
+pub fn cumulative_prices_at(
+    storage: &dyn Storage,
+    latest: Observation,
+    timestamp: u64,
+) -> StdResult<Option<(Uint128, Uint128)>> {
+    let count = OBSERVATIONS_COUNT.may_load(storage)?.unwrap_or_default();
+    let stored = count.min(OBSERVATIONS_CAPACITY);
+    // the i-th stored observation from the oldest one
+    let load = |i: u64| -> StdResult<Observation> {
+        OBSERVATIONS.load(
+            storage,
+            U64Key::new((count - stored + i) % OBSERVATIONS_CAPACITY),
+        )
+    };
+
+    if stored == 0 || load(0)?.timestamp > timestamp {
+        return Ok(None);
+    }
+
+    // binary search for the newest observation made at or before the timestamp
+    let (mut low, mut high) = (0, stored - 1);
+    while low < high {
+        let mid = (low + high + 1) / 2;
+        if load(mid)?.timestamp <= timestamp {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let before = load(low)?;
+    let after = if low + 1 < stored {
+        load(low + 1)?
+    } else {
+        latest
+    };
+
+    if before.timestamp == timestamp || after.timestamp <= before.timestamp {
+        return Ok(Some((before.price0_cumulative, before.price1_cumulative)));
+    }
+
+    let elapsed = Uint128::from(timestamp - before.timestamp);
+    let period = Uint128::from(after.timestamp - before.timestamp);
+    let interpolate = |before: Uint128, after: Uint128| {
+        before.wrapping_add(after.wrapping_sub(before).multiply_ratio(elapsed, period))
+    };
+
+    Ok(Some((
+        interpolate(before.price0_cumulative, after.price0_cumulative),
+        interpolate(before.price1_cumulative, after.price1_cumulative),
+    )))
+}
+
+/// ## Description
+/// Returns the time-weighted average prices of stLuna and bLuna over the window ending at the latest
+/// accumulator value. Returns [`None`] if the window is not covered by the stored observations.
+/// ## Params
+/// * **storage** is the object of type [`Storage`].
+///
+/// * **latest** is the object of type [`Observation`]. The accumulator value at the end of the window.
+///
+/// * **window_seconds** is the length of the window in seconds, must not be zero.
+pub fn twap_prices(
+    storage: &dyn Storage,
+    latest: Observation,
+    window_seconds: u64,
+) -> StdResult<Option<(Decimal, Decimal)>> {
+    if latest.timestamp < window_seconds {
+        return Ok(None);
+    }
+
+    let (price0_cumulative_start, price1_cumulative_start) =
+        match cumulative_prices_at(storage, latest.clone(), latest.timestamp - window_seconds)? {
+            Some(cumulative_prices) => cumulative_prices,
+            None => return Ok(None),
+        };
+
+    let denominator = Uint128::from(window_seconds)
+        .checked_mul(Uint128::from(10u128.pow(TWAP_PRECISION.into())))?;
+
+    Ok(Some((
+        Decimal::from_ratio(
+            latest
+                .price0_cumulative
+                .wrapping_sub(price0_cumulative_start),
+            denominator,
+        ),
+        Decimal::from_ratio(
+            latest
+                .price1_cumulative
+                .wrapping_sub(price1_cumulative_start),
+            denominator,
+        ),
+    )))
+}
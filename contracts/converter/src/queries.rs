@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-only
 // Copyright Lido
 
+use crate::simulation::HubSnapshot;
 use cosmwasm_std::{to_binary, Addr, Deps, QueryRequest, StdResult, Uint128, WasmQuery};
 use cw20::{BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
 
@@ -52,6 +53,37 @@ pub fn query_current_batch(
     Ok(batch)
 }
 
+/// ## Description
+/// Returns the Lido Hub data required to simulate conversions
+/// ## Params
+/// * **deps** is the object of type [`Deps`].
+///
+/// * **hub_address** is the object of type [`Addr`].
+///
+/// * **bluna_address** is the object of type [`Addr`].
+pub fn query_hub_snapshot(
+    deps: Deps,
+    hub_address: Addr,
+    bluna_address: Addr,
+) -> StdResult<HubSnapshot> {
+    let state = query_hub_state(deps, hub_address.clone())?;
+    let params = query_hub_params(deps, hub_address.clone())?;
+    let current_batch = query_current_batch(deps, hub_address)?;
+    let total_bluna_supply = query_total_tokens_issued(deps, bluna_address)?;
+
+    Ok(HubSnapshot {
+        stluna_exchange_rate: state.stluna_exchange_rate,
+        bluna_exchange_rate: state.bluna_exchange_rate,
+        er_threshold: params.er_threshold,
+        peg_recovery_fee: params.peg_recovery_fee,
+        total_bluna_supply,
+        total_bond_bluna_amount: state.total_bond_bluna_amount,
+        requested_bluna_with_fee: current_batch.requested_bluna_with_fee,
+        paused: params.paused.unwrap_or(false),
+        last_index_modification: state.last_index_modification,
+    })
+}
+
 /// ## Description
 /// Returns total issued CW20 tokens amount
 /// ## Params
@@ -1,85 +1,209 @@
 // SPDX-License-Identifier: GPL-3.0-only
 // Copyright Lido
 
+use crate::error::ContractError;
 use crate::math::{decimal_division, decimal_division_in_256, decimal_multiplication_in_256};
-use crate::queries::{
-    query_current_batch, query_hub_params, query_hub_state, query_total_tokens_issued,
-};
-use crate::state::Config;
-use cosmwasm_std::{Decimal, Deps, StdResult, Uint128};
+use cosmwasm_std::{Decimal, Uint128};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::ops::Mul;
 
 /// ## Description
-/// Returns how much bluna user will get for provided stluna amount
+/// Describes the Lido Hub data required to simulate conversions.
+/// It is loaded once per operation (see [`crate::queries::query_hub_snapshot`]) to avoid querying
+/// the Hub for every simulation, so all the functions below are pure.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HubSnapshot {
+    /// the Hub's stLuna exchange rate
+    pub stluna_exchange_rate: Decimal,
+    /// the Hub's bLuna exchange rate
+    pub bluna_exchange_rate: Decimal,
+    /// the Hub's threshold of the bLuna exchange rate to apply the peg recovery fee
+    pub er_threshold: Decimal,
+    /// the Hub's peg recovery fee rate
+    pub peg_recovery_fee: Decimal,
+    /// the total amount of issued bLuna tokens
+    pub total_bluna_supply: Uint128,
+    /// the total amount of Luna bonded for bLuna
+    pub total_bond_bluna_amount: Uint128,
+    /// the amount of bLuna requested to unbond in the current batch
+    pub requested_bluna_with_fee: Uint128,
+    /// true if the Hub is paused
+    pub paused: bool,
+    /// the last time (in seconds) the Hub updated its exchange rates
+    pub last_index_modification: u64,
+}
+
+/// ## Description
+/// Describes the result of a conversion simulation
+pub struct ConversionSimulation {
+    /// the amount of tokens the user will get
+    pub return_amount: Uint128,
+    /// the Hub's threshold of the bLuna exchange rate to apply the peg recovery fee
+    pub er_threshold: Decimal,
+    /// the maximum peg recovery fee (in bLuna)
+    pub max_peg_fee: Uint128,
+    /// the peg recovery fee required to recover the bLuna peg (in bLuna)
+    pub required_peg_fee: Uint128,
+    /// the peg recovery fee charged by the Hub (in bLuna)
+    pub peg_fee: Uint128,
+    /// the converter fee (in ask tokens)
+    pub converter_fee: Uint128,
+    /// the peg recovery fee in ask tokens plus the converter fee
+    pub commission_amount: Uint128,
+    /// the amount of ask tokens the offer converts to at the exchange rates without any fee
+    pub fee_free_return_amount: Uint128,
+    /// the shortfall of the return amount plus the commission against the fee-free return amount
+    pub spread_amount: Uint128,
+    /// the stLuna exchange rate used for the conversion
+    pub stluna_exchange_rate: Decimal,
+    /// the bLuna exchange rate used for the conversion
+    pub bluna_exchange_rate: Decimal,
+}
+
+/// ## Description
+/// Simulates the conversion of provided stluna amount to bluna
 /// ## Params
-/// * **deps** is the object of type [`Deps`],
-///
-/// * **config** is the object of type [`Config`],
+/// * **hub** is the object of type [`HubSnapshot`],
 ///
 /// * **stluna_amount** is the object of type [`Uint128`]
-pub fn convert_stluna_to_bluna(
-    deps: Deps,
-    config: Config,
+pub fn simulate_stluna_to_bluna(
+    hub: &HubSnapshot,
     stluna_amount: Uint128,
-) -> StdResult<Uint128> {
-    let state = query_hub_state(deps, config.hub_addr.clone())?;
-    let params = query_hub_params(deps, config.hub_addr.clone())?;
-    let current_batch = query_current_batch(deps, config.hub_addr)?;
-    let total_bluna_supply = query_total_tokens_issued(deps, config.bluna_addr)?;
+) -> Result<ConversionSimulation, ContractError> {
+    let denom_equiv = hub.stluna_exchange_rate.mul(stluna_amount);
 
-    let threshold = params.er_threshold;
-    let recovery_fee = params.peg_recovery_fee;
+    simulate_luna_to_bluna(hub, denom_equiv)
+}
 
-    let denom_equiv = state.stluna_exchange_rate.mul(stluna_amount);
+/// ## Description
+/// Simulates bonding of provided luna amount for bluna.
+/// The Hub mints bluna for converted stluna in the same way, so it is also used for conversions.
+/// ## Params
+/// * **hub** is the object of type [`HubSnapshot`],
+///
+/// * **denom_equiv** is the object of type [`Uint128`]
+pub fn simulate_luna_to_bluna(
+    hub: &HubSnapshot,
+    denom_equiv: Uint128,
+) -> Result<ConversionSimulation, ContractError> {
+    let threshold = hub.er_threshold;
+    let recovery_fee = hub.peg_recovery_fee;
 
-    let bluna_to_mint = decimal_division(denom_equiv, state.bluna_exchange_rate);
-    let requested_bluna_with_fee = current_batch.requested_bluna_with_fee;
+    let bluna_to_mint = decimal_division(denom_equiv, hub.bluna_exchange_rate);
+    let requested_bluna_with_fee = hub.requested_bluna_with_fee;
 
     let mut bluna_mint_amount_with_fee = bluna_to_mint;
-    if state.bluna_exchange_rate < threshold {
-        let max_peg_fee = bluna_to_mint * recovery_fee;
-        let required_peg_fee = (total_bluna_supply + bluna_to_mint + requested_bluna_with_fee)
-            - (state.total_bond_bluna_amount + denom_equiv);
-        let peg_fee = Uint128::min(max_peg_fee, required_peg_fee);
-        bluna_mint_amount_with_fee = bluna_to_mint.checked_sub(peg_fee)?;
+    let mut max_peg_fee = Uint128::zero();
+    let mut required_peg_fee = Uint128::zero();
+    let mut peg_fee = Uint128::zero();
+    if hub.bluna_exchange_rate < threshold {
+        max_peg_fee = bluna_to_mint * recovery_fee;
+        // no fee is required if the conversion itself recovers the peg
+        required_peg_fee = hub
+            .total_bluna_supply
+            .checked_add(bluna_to_mint)?
+            .checked_add(requested_bluna_with_fee)?
+            .saturating_sub(hub.total_bond_bluna_amount.checked_add(denom_equiv)?);
+        peg_fee = Uint128::min(max_peg_fee, required_peg_fee);
+        bluna_mint_amount_with_fee = checked_sub(bluna_to_mint, peg_fee)?;
     }
 
-    Ok(bluna_mint_amount_with_fee)
+    // the peg fee is charged in bLuna, so it is already in ask tokens and the spread is always zero
+    let spread_amount = bluna_to_mint.saturating_sub(bluna_mint_amount_with_fee + peg_fee);
+
+    Ok(ConversionSimulation {
+        return_amount: bluna_mint_amount_with_fee,
+        er_threshold: threshold,
+        max_peg_fee,
+        required_peg_fee,
+        peg_fee,
+        converter_fee: Uint128::zero(),
+        commission_amount: peg_fee,
+        fee_free_return_amount: bluna_to_mint,
+        spread_amount,
+        stluna_exchange_rate: hub.stluna_exchange_rate,
+        bluna_exchange_rate: hub.bluna_exchange_rate,
+    })
 }
 
 /// ## Description
-/// Returns how much stluna user have to provide to get **bluna_amount**
+/// Simulates bonding of provided luna amount for stluna. The Hub charges no fee for it.
 /// ## Params
-/// * **deps** is the object of type [`Deps`],
+/// * **hub** is the object of type [`HubSnapshot`],
 ///
-/// * **config** is the object of type [`Config`],
+/// * **luna_amount** is the object of type [`Uint128`]
+pub fn simulate_luna_to_stluna(
+    hub: &HubSnapshot,
+    luna_amount: Uint128,
+) -> Result<ConversionSimulation, ContractError> {
+    let stluna_to_mint = decimal_division(luna_amount, hub.stluna_exchange_rate);
+
+    Ok(ConversionSimulation {
+        return_amount: stluna_to_mint,
+        er_threshold: hub.er_threshold,
+        max_peg_fee: Uint128::zero(),
+        required_peg_fee: Uint128::zero(),
+        peg_fee: Uint128::zero(),
+        converter_fee: Uint128::zero(),
+        commission_amount: Uint128::zero(),
+        fee_free_return_amount: stluna_to_mint,
+        spread_amount: Uint128::zero(),
+        stluna_exchange_rate: hub.stluna_exchange_rate,
+        bluna_exchange_rate: hub.bluna_exchange_rate,
+    })
+}
+
+/// ## Description
+/// Returns the minimal amount of stluna user have to provide to get at least **asked_bluna_amount**
+/// from [`simulate_stluna_to_bluna`]
+/// ## Params
+/// * **hub** is the object of type [`HubSnapshot`],
 ///
-/// * **stluna_amount** is the object of type [`Uint128`]
+/// * **asked_bluna_amount** is the object of type [`Uint128`]
 pub fn get_required_stluna(
-    deps: Deps,
-    config: Config,
+    hub: &HubSnapshot,
     asked_bluna_amount: Uint128,
-) -> StdResult<Uint128> {
-    let state = query_hub_state(deps, config.hub_addr.clone())?;
-    let params = query_hub_params(deps, config.hub_addr.clone())?;
-    let current_batch = query_current_batch(deps, config.hub_addr)?;
-    let total_bluna_supply = query_total_tokens_issued(deps, config.bluna_addr)?;
+) -> Result<Uint128, ContractError> {
+    let estimate = estimate_required_stluna(hub, asked_bluna_amount)?;
+    find_minimal_offer_amount(estimate, asked_bluna_amount, |offer_amount| {
+        Ok(simulate_stluna_to_bluna(hub, offer_amount)?.return_amount)
+    })
+}
 
-    let threshold = params.er_threshold;
-    let recovery_fee = params.peg_recovery_fee;
+/// ## Description
+/// Estimates how much stluna user have to provide to get **bluna_amount**, ignoring the truncations
+/// ## Params
+/// * **hub** is the object of type [`HubSnapshot`],
+///
+/// * **asked_bluna_amount** is the object of type [`Uint128`]
+fn estimate_required_stluna(
+    hub: &HubSnapshot,
+    asked_bluna_amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let threshold = hub.er_threshold;
+    let recovery_fee = hub.peg_recovery_fee;
 
-    let requested_bluna_with_fee = current_batch.requested_bluna_with_fee;
+    let requested_bluna_with_fee = hub.requested_bluna_with_fee;
 
     let denom_equiv: Uint128;
 
     // just a reversed calculations from the function above
-    if state.bluna_exchange_rate < threshold {
+    if hub.bluna_exchange_rate < threshold {
+        let denom_equiv_without_fee = hub.bluna_exchange_rate * asked_bluna_amount;
+
+        // the required fee can not be negative, so the Hub charges no fee
+        // if the bonded amount covers the supply even without this conversion
         let denom_equiv_with_applied_required_fee = asked_bluna_amount
-            + (total_bluna_supply + requested_bluna_with_fee)
-            - (state.total_bond_bluna_amount);
+            .checked_add(hub.total_bluna_supply)?
+            .checked_add(requested_bluna_with_fee)?
+            .checked_sub(hub.total_bond_bluna_amount)
+            .map_or(denom_equiv_without_fee, |denom_equiv| {
+                Uint128::max(denom_equiv, denom_equiv_without_fee)
+            });
 
         let denom_equiv_with_applied_max_peg_fee =
-            decimal_division_in_256(state.bluna_exchange_rate, Decimal::one() - recovery_fee)
+            decimal_division_in_256(hub.bluna_exchange_rate, fee_complement(recovery_fee)?)
                 * asked_bluna_amount;
 
         denom_equiv = Uint128::min(
@@ -87,99 +211,124 @@ pub fn get_required_stluna(
             denom_equiv_with_applied_required_fee,
         );
     } else {
-        denom_equiv = state.bluna_exchange_rate * asked_bluna_amount;
+        denom_equiv = hub.bluna_exchange_rate * asked_bluna_amount;
     }
 
-    let stluna_amount = decimal_division(denom_equiv, state.stluna_exchange_rate);
+    let stluna_amount = decimal_division(denom_equiv, hub.stluna_exchange_rate);
 
     Ok(stluna_amount)
 }
 
 /// ## Description
-/// Returns how much stluna user will get for provided bluna amount
+/// Simulates the conversion of provided bluna amount to stluna
 /// ## Params
-/// * **deps** is the object of type [`Deps`],
-///
-/// * **config** is the object of type [`Config`],
+/// * **hub** is the object of type [`HubSnapshot`],
 ///
 /// * **bluna_amount** is the object of type [`Uint128`]
-pub fn convert_bluna_to_stluna(
-    deps: Deps,
-    config: Config,
+pub fn simulate_bluna_to_stluna(
+    hub: &HubSnapshot,
     bluna_amount: Uint128,
-) -> StdResult<Uint128> {
-    let state = query_hub_state(deps, config.hub_addr.clone())?;
-    let params = query_hub_params(deps, config.hub_addr.clone())?;
-    let current_batch = query_current_batch(deps, config.hub_addr.clone())?;
-    let total_bluna_supply = query_total_tokens_issued(deps, config.bluna_addr)?;
-
-    let threshold = params.er_threshold;
-    let recovery_fee = params.peg_recovery_fee;
+) -> Result<ConversionSimulation, ContractError> {
+    let threshold = hub.er_threshold;
+    let recovery_fee = hub.peg_recovery_fee;
 
     // Apply peg recovery fee
     let bluna_amount_with_fee: Uint128;
-    if state.bluna_exchange_rate < threshold {
-        let max_peg_fee = bluna_amount * recovery_fee;
-        let required_peg_fee = (total_bluna_supply + current_batch.requested_bluna_with_fee)
-            .checked_sub(state.total_bond_bluna_amount)?;
-        let peg_fee = Uint128::min(max_peg_fee, required_peg_fee);
-        bluna_amount_with_fee = bluna_amount.checked_sub(peg_fee)?;
+    let mut max_peg_fee = Uint128::zero();
+    let mut required_peg_fee = Uint128::zero();
+    let mut peg_fee = Uint128::zero();
+    if hub.bluna_exchange_rate < threshold {
+        max_peg_fee = bluna_amount * recovery_fee;
+        required_peg_fee = hub
+            .total_bluna_supply
+            .checked_add(hub.requested_bluna_with_fee)?
+            .saturating_sub(hub.total_bond_bluna_amount);
+        peg_fee = Uint128::min(max_peg_fee, required_peg_fee);
+        bluna_amount_with_fee = checked_sub(bluna_amount, peg_fee)?;
     } else {
         bluna_amount_with_fee = bluna_amount;
     }
 
-    let denom_equiv = state.bluna_exchange_rate.mul(bluna_amount_with_fee);
-
-    let stluna_to_mint = decimal_division(denom_equiv, state.stluna_exchange_rate);
-
-    Ok(stluna_to_mint)
+    let denom_equiv = hub.bluna_exchange_rate.mul(bluna_amount_with_fee);
+
+    let stluna_to_mint = decimal_division(denom_equiv, hub.stluna_exchange_rate);
+
+    // the peg fee is charged in bLuna, so we have to convert it to stLuna
+    let commission_amount = decimal_division(
+        hub.bluna_exchange_rate.mul(peg_fee),
+        hub.stluna_exchange_rate,
+    );
+    let stluna_to_mint_without_fee = decimal_division(
+        hub.bluna_exchange_rate.mul(bluna_amount),
+        hub.stluna_exchange_rate,
+    );
+    let spread_amount =
+        stluna_to_mint_without_fee.saturating_sub(stluna_to_mint + commission_amount);
+
+    Ok(ConversionSimulation {
+        return_amount: stluna_to_mint,
+        er_threshold: threshold,
+        max_peg_fee,
+        required_peg_fee,
+        peg_fee,
+        converter_fee: Uint128::zero(),
+        commission_amount,
+        fee_free_return_amount: stluna_to_mint_without_fee,
+        spread_amount,
+        stluna_exchange_rate: hub.stluna_exchange_rate,
+        bluna_exchange_rate: hub.bluna_exchange_rate,
+    })
 }
 
 /// ## Description
-/// Returns how much bluna user have to provide to get **stluna_amount**
+/// Returns the minimal amount of bluna user have to provide to get at least **asked_stluna_amount**
+/// from [`simulate_bluna_to_stluna`]
 /// ## Params
-/// * **deps** is the object of type [`Deps`],
-///
-/// * **config** is the object of type [`Config`],
+/// * **hub** is the object of type [`HubSnapshot`],
 ///
-/// * **stluna_amount** is the object of type [`Uint128`]
+/// * **asked_stluna_amount** is the object of type [`Uint128`]
 pub fn get_required_bluna(
-    deps: Deps,
-    config: Config,
+    hub: &HubSnapshot,
     asked_stluna_amount: Uint128,
-) -> StdResult<Uint128> {
-    let state = query_hub_state(deps, config.hub_addr.clone())?;
-    let params = query_hub_params(deps, config.hub_addr.clone())?;
-    let current_batch = query_current_batch(deps, config.hub_addr)?;
-    let total_bluna_supply = query_total_tokens_issued(deps, config.bluna_addr)?;
+) -> Result<Uint128, ContractError> {
+    let estimate = estimate_required_bluna(hub, asked_stluna_amount)?;
+    find_minimal_offer_amount(estimate, asked_stluna_amount, |offer_amount| {
+        Ok(simulate_bluna_to_stluna(hub, offer_amount)?.return_amount)
+    })
+}
 
-    let threshold = params.er_threshold;
-    let recovery_fee = params.peg_recovery_fee;
+/// ## Description
+/// Estimates how much bluna user have to provide to get **stluna_amount**, ignoring the truncations
+/// ## Params
+/// * **hub** is the object of type [`HubSnapshot`],
+///
+/// * **asked_stluna_amount** is the object of type [`Uint128`]
+fn estimate_required_bluna(
+    hub: &HubSnapshot,
+    asked_stluna_amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let threshold = hub.er_threshold;
+    let recovery_fee = hub.peg_recovery_fee;
 
-    let offer_bluna =
-        decimal_division_in_256(state.stluna_exchange_rate, state.bluna_exchange_rate)
-            .mul(asked_stluna_amount);
+    let offer_bluna = decimal_division_in_256(hub.stluna_exchange_rate, hub.bluna_exchange_rate)
+        .mul(asked_stluna_amount);
 
     let mut offer_bluna_with_fee = offer_bluna;
 
     // just a reversed calculations from the function above
-    if state.bluna_exchange_rate < threshold {
+    if hub.bluna_exchange_rate < threshold {
         let offer_bluna_with_max_peg_fee = decimal_division_in_256(
-            state.stluna_exchange_rate,
-            decimal_multiplication_in_256(state.bluna_exchange_rate, Decimal::one() - recovery_fee),
+            hub.stluna_exchange_rate,
+            decimal_multiplication_in_256(hub.bluna_exchange_rate, fee_complement(recovery_fee)?),
         )
         .mul(asked_stluna_amount);
 
-        let required_peg_fee = (total_bluna_supply + current_batch.requested_bluna_with_fee)
-            .checked_sub(state.total_bond_bluna_amount)?;
+        let required_peg_fee = hub
+            .total_bluna_supply
+            .checked_add(hub.requested_bluna_with_fee)?
+            .saturating_sub(hub.total_bond_bluna_amount);
 
-        let bluna_amount = decimal_multiplication_in_256(
-            decimal_division_in_256(state.stluna_exchange_rate, state.bluna_exchange_rate),
-            state.stluna_exchange_rate,
-        )
-        .mul(asked_stluna_amount);
-
-        let offer_bluna_with_required_peg_fee = bluna_amount + required_peg_fee;
+        let offer_bluna_with_required_peg_fee = offer_bluna.checked_add(required_peg_fee)?;
 
         offer_bluna_with_fee = Uint128::min(
             offer_bluna_with_max_peg_fee,
@@ -189,3 +338,119 @@ pub fn get_required_bluna(
 
     Ok(offer_bluna_with_fee)
 }
+
+/// ## Description
+/// Deducts the converter fee from the return amount of the simulation. The fee is added to the commission.
+/// ## Params
+/// * **simulation** is the object of type [`ConversionSimulation`],
+///
+/// * **fee_rate** is the object of type [`Decimal`]
+pub fn apply_converter_fee(
+    mut simulation: ConversionSimulation,
+    fee_rate: Decimal,
+) -> Result<ConversionSimulation, ContractError> {
+    let converter_fee = simulation.return_amount * fee_rate;
+    simulation.return_amount = checked_sub(simulation.return_amount, converter_fee)?;
+    simulation.commission_amount = simulation.commission_amount.checked_add(converter_fee)?;
+    simulation.converter_fee = converter_fee;
+
+    Ok(simulation)
+}
+
+/// ## Description
+/// Returns the minimal amount the Hub has to mint so that at least **asked_amount** is left after
+/// the converter fee is deducted by [`apply_converter_fee`]
+/// ## Params
+/// * **asked_amount** is the object of type [`Uint128`],
+///
+/// * **fee_rate** is the object of type [`Decimal`]
+pub fn get_required_return_before_fee(
+    asked_amount: Uint128,
+    fee_rate: Decimal,
+) -> Result<Uint128, ContractError> {
+    let estimate = decimal_division(asked_amount, fee_complement(fee_rate)?);
+    find_minimal_offer_amount(estimate, asked_amount, |return_amount| {
+        checked_sub(return_amount, return_amount * fee_rate)
+    })
+}
+
+/// ## Description
+/// Returns the minimal offer amount which **simulate** converts to at least **asked_amount**.
+/// The forward simulation truncates on every step, so the analytical **estimate** may be off by a few units
+/// in both directions. The simulation never returns less for a bigger offer, so the estimate is corrected
+/// by an exponential search for the bounds followed by a binary search between them.
+/// ## Params
+/// * **estimate** is the object of type [`Uint128`]. The offer amount to start the search from.
+///
+/// * **asked_amount** is the object of type [`Uint128`].
+///
+/// * **simulate** is the forward simulation returning the return amount for the offer amount.
+fn find_minimal_offer_amount<F>(
+    estimate: Uint128,
+    asked_amount: Uint128,
+    simulate: F,
+) -> Result<Uint128, ContractError>
+where
+    F: Fn(Uint128) -> Result<Uint128, ContractError>,
+{
+    if asked_amount.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    // the high bound is enough to get the asked amount...
+    let mut high = estimate.u128();
+    let mut step = 1u128;
+    while simulate(Uint128::from(high))? < asked_amount {
+        high = high
+            .checked_add(step)
+            .ok_or(ContractError::SimulationUnderflow {})?;
+        step = step.saturating_mul(2);
+    }
+
+    // ...and the low bound is not, which is always true for the zero offer
+    let mut low = high;
+    let mut step = 1u128;
+    loop {
+        low = low.saturating_sub(step);
+        if low == 0 || simulate(Uint128::from(low))? < asked_amount {
+            break;
+        }
+        high = low;
+        step = step.saturating_mul(2);
+    }
+
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if simulate(Uint128::from(middle))? < asked_amount {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    Ok(Uint128::from(high))
+}
+
+/// ## Description
+/// Returns the share of the converted amount left after the maximum peg recovery fee.
+/// Returns a [`ContractError::SimulationUnderflow`] if the fee takes the whole amount.
+/// ## Params
+/// * **recovery_fee** is the object of type [`Decimal`].
+fn fee_complement(recovery_fee: Decimal) -> Result<Decimal, ContractError> {
+    if recovery_fee >= Decimal::one() {
+        return Err(ContractError::SimulationUnderflow {});
+    }
+    Ok(Decimal::one() - recovery_fee)
+}
+
+/// ## Description
+/// Returns **amount** - **fee** or a [`ContractError::SimulationUnderflow`] if the fee exceeds the amount.
+/// ## Params
+/// * **amount** is the object of type [`Uint128`].
+///
+/// * **fee** is the object of type [`Uint128`].
+fn checked_sub(amount: Uint128, fee: Uint128) -> Result<Uint128, ContractError> {
+    amount
+        .checked_sub(fee)
+        .map_err(|_| ContractError::SimulationUnderflow {})
+}
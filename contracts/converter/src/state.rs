@@ -2,10 +2,13 @@
 // Copyright Astroport
 // Copyright Lido
 
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::Item;
+use crate::simulation::HubSnapshot;
+use astroport::asset::AssetInfo;
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use cw_storage_plus::{Item, Map, U64Key};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// ## Description
 /// This structure describes the main control config of pair.
@@ -17,6 +20,12 @@ pub struct Config {
     pub price0_cumulative_last: Uint128,
     /// The last cumulative price 1 asset in pool
     pub price1_cumulative_last: Uint128,
+    /// The price 0 asset in pool at the last time block, zero if unknown
+    pub price0_last: Uint128,
+    /// The price 1 asset in pool at the last time block, zero if unknown
+    pub price1_last: Uint128,
+    /// the source of the time the prices changed at
+    pub accumulator_mode: AccumulatorMode,
 
     /// the Lido contract addresses
     pub hub_addr: Addr,
@@ -24,20 +33,160 @@ pub struct Config {
     pub bluna_addr: Addr,
 
     pub owner: Addr,
+
+    /// the token the Hub mints for native LUNA offered to the swap
+    pub native_swap_mode: NativeSwapMode,
+
+    /// the share of the converted tokens charged by the converter
+    pub fee_rate: Decimal,
+    /// the recipient of the converter fee
+    pub fee_collector: Option<Addr>,
+
+    /// the address which can pause and unpause swaps in addition to the owner
+    pub guardian: Option<Addr>,
+    /// true if swaps are paused
+    pub paused: bool,
+
+    /// the minimum time (in seconds) between the permissionless price updates, 0 if not limited
+    pub min_price_update_interval: u64,
+
+    /// the relative change of an exchange rate between two snapshots to emit the rate jump event, 0 if disabled
+    pub rate_jump_threshold: Decimal,
+
+    /// the relative deviation of the Hub's prices from the TWAP to halt swaps at, 0 if disabled
+    pub circuit_breaker_threshold: Decimal,
+    /// the length (in seconds) of the TWAP window the Hub's prices are compared with
+    pub circuit_breaker_window: u64,
+    /// true if the circuit breaker was tripped and swaps are halted until the guardian resets it
+    pub circuit_breaker_tripped: bool,
+    /// the last time (in seconds) the circuit breaker was reset
+    pub circuit_breaker_reset_time: u64,
+
+    /// the reserves reported by the pool queries
+    pub reserve_model: ReserveModel,
+}
+
+/// ## Description
+/// This enum describes how the native LUNA offered to the swap is handled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NativeSwapMode {
+    /// native LUNA is not accepted
+    Disabled,
+    /// native LUNA is bonded for bLuna
+    Bluna,
+    /// native LUNA is bonded for stLuna
+    Stluna,
+}
+
+impl fmt::Display for NativeSwapMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NativeSwapMode::Disabled => write!(f, "disabled"),
+            NativeSwapMode::Bluna => write!(f, "bluna"),
+            NativeSwapMode::Stluna => write!(f, "stluna"),
+        }
+    }
+}
+
+/// ## Description
+/// This enum describes when the prices accumulated by the contract are considered changed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AccumulatorMode {
+    /// the prices change at the time of the accumulation
+    BlockTime,
+    /// the prices change at the time the Hub updated its exchange rates (`last_index_modification`)
+    HubIndexModification,
+}
+
+impl fmt::Display for AccumulatorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccumulatorMode::BlockTime => write!(f, "block_time"),
+            AccumulatorMode::HubIndexModification => write!(f, "hub_index_modification"),
+        }
+    }
+}
+
+/// ## Description
+/// This enum describes the reserves reported by the pool queries.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReserveModel {
+    /// the total supplies of the tokens
+    TotalSupply,
+    /// the virtual reserves worth the amount of LUNA bonded for bLuna at the Hub's exchange rates,
+    /// so the constant product price equals the conversion rate
+    ExchangeRate,
+}
+
+impl fmt::Display for ReserveModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReserveModel::TotalSupply => write!(f, "total_supply"),
+            ReserveModel::ExchangeRate => write!(f, "exchange_rate"),
+        }
+    }
 }
 
 /// ## Description
 /// Stores config at the given key
 pub const CONFIG: Item<Config> = Item::new("config");
 
+/// ## Description
+/// This structure describes a request to change ownership of the contract.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipProposal {
+    /// the proposed owner
+    pub owner: Addr,
+    /// the time (in seconds) after which the proposal expires
+    pub ttl: u64,
+}
+
+/// ## Description
+/// Stores the latest proposal to change ownership
+pub const OWNERSHIP_PROPOSAL: Item<OwnershipProposal> = Item::new("ownership_proposal");
+
 /// ## Description
 /// Describes user's swap request for processing in reply handler
-/// (<USER_ADDR>, <ASK_TOKEN_ADDR>)
-pub type SwapRequest = (Addr, Addr);
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingSwap {
+    /// the sender of the swap operation
+    pub sender: Addr,
+    /// the recipient of the converted tokens
+    pub recipient: Addr,
+    /// the offered asset, a token or native LUNA
+    pub offer_asset_info: AssetInfo,
+    /// the address of the converted (ask) token contract
+    pub ask_token: Addr,
+    /// the ask token balance of the contract before the conversion
+    pub ask_token_balance_before: Uint128,
+    /// the amount of offered tokens
+    pub offer_amount: Uint128,
+    /// the amount of ask tokens the offer converts to without any fee, simulated before the conversion
+    pub fee_free_return: Uint128,
+    /// the peg recovery fee (in bLuna) returned by the simulation before the conversion
+    pub peg_fee: Uint128,
+    /// the peg recovery fee in ask tokens returned by the simulation before the conversion
+    pub commission_amount: Uint128,
+    /// the Hub's state the conversion was simulated with, so the reply handler does not query the Hub again
+    pub hub: HubSnapshot,
+    /// the belief price provided by the user
+    pub belief_price: Option<Decimal>,
+    /// the max spread provided by the user
+    pub max_spread: Option<Decimal>,
+    /// the minimum amount of ask tokens the recipient must get
+    pub minimum_receive: Option<Uint128>,
+}
+
+/// ## Description
+/// Stores pending swaps by the reply id of the conversion submessage
+pub const PENDING_SWAPS: Map<U64Key, PendingSwap> = Map::new("pending_swaps");
 
 /// ## Description
-/// Stores addr of recipient who should get converted tokens and address of converted tokens contract
-pub const SWAP_REQUEST: Item<SwapRequest> = Item::new("swap_recipient");
+/// Stores the reply id to be used for the next conversion submessage
+pub const NEXT_SWAP_REPLY_ID: Item<u64> = Item::new("next_swap_reply_id");
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
@@ -46,4 +195,54 @@ pub struct ConfigResponse {
     pub bluna_address: Addr,
     pub owner: Addr,
     pub block_time_last: u64,
+    pub native_swap_mode: NativeSwapMode,
+    pub fee_rate: Decimal,
+    pub fee_collector: Option<Addr>,
+    pub guardian: Option<Addr>,
+    pub paused: bool,
+    pub min_price_update_interval: u64,
+    pub accumulator_mode: AccumulatorMode,
+    pub rate_jump_threshold: Decimal,
+    pub circuit_breaker_threshold: Decimal,
+    pub circuit_breaker_window: u64,
+    pub circuit_breaker_tripped: bool,
+    pub reserve_model: ReserveModel,
 }
+
+/// ## Description
+/// This structure describes an observation of the cumulative prices.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Observation {
+    /// the time of the observation (in seconds)
+    pub timestamp: u64,
+    /// the cumulative price of stLuna in bLuna at the time of the observation
+    pub price0_cumulative: Uint128,
+    /// the cumulative price of bLuna in stLuna at the time of the observation
+    pub price1_cumulative: Uint128,
+}
+
+/// ## Description
+/// Stores the ring buffer of the cumulative price observations by the slot index
+pub const OBSERVATIONS: Map<U64Key, Observation> = Map::new("observations");
+
+/// ## Description
+/// Stores the total number of the cumulative price observations ever made
+pub const OBSERVATIONS_COUNT: Item<u64> = Item::new("observations_count");
+
+/// ## Description
+/// This structure describes a snapshot of the Hub's exchange rates.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RateSnapshot {
+    /// the block height of the snapshot
+    pub height: u64,
+    /// the block time (in seconds) of the snapshot
+    pub timestamp: u64,
+    /// the Hub's stLuna exchange rate
+    pub stluna_exchange_rate: Decimal,
+    /// the Hub's bLuna exchange rate
+    pub bluna_exchange_rate: Decimal,
+}
+
+/// ## Description
+/// Stores the snapshots of the Hub's exchange rates by the block height
+pub const RATE_HISTORY: Map<U64Key, RateSnapshot> = Map::new("rate_history");
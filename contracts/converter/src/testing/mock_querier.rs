@@ -9,7 +9,8 @@ use cosmwasm_std::{
     from_binary, from_slice, to_binary, Coin, ContractResult, Decimal, Empty, OwnedDeps, Querier,
     QuerierResult, QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
 };
-use cw20::TokenInfoResponse;
+use cw20::{BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
+use std::collections::HashMap;
 use std::str::FromStr;
 
 pub const MOCK_HUB_CONTRACT_ADDR: &str = "hub";
@@ -32,6 +33,11 @@ pub fn mock_dependencies(
 
 pub struct WasmMockQuerier {
     base: MockQuerier<Empty>,
+    token_balances: HashMap<String, HashMap<String, Uint128>>,
+    hub_paused: Option<bool>,
+    hub_last_index_modification: u64,
+    hub_stluna_exchange_rate: Decimal,
+    hub_bluna_exchange_rate: Decimal,
 }
 
 impl Querier for WasmMockQuerier {
@@ -54,22 +60,42 @@ impl WasmMockQuerier {
     pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
         match &request {
             QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
-                if *contract_addr == MOCK_STLUNA_TOKEN_CONTRACT_ADDR {
-                    let token_inf: TokenInfoResponse = TokenInfoResponse {
-                        name: "stluna".to_string(),
-                        symbol: "stLUNA".to_string(),
-                        decimals: 6,
-                        total_supply: Uint128::new(10000u128),
-                    };
-                    SystemResult::Ok(ContractResult::Ok(to_binary(&token_inf).unwrap()))
-                } else if *contract_addr == MOCK_BLUNA_TOKEN_CONTRACT_ADDR {
-                    let token_inf: TokenInfoResponse = TokenInfoResponse {
-                        name: "bluna".to_string(),
-                        symbol: "bLUNA".to_string(),
-                        decimals: 6,
-                        total_supply: Uint128::new(10000u128),
-                    };
-                    SystemResult::Ok(ContractResult::Ok(to_binary(&token_inf).unwrap()))
+                if *contract_addr == MOCK_STLUNA_TOKEN_CONTRACT_ADDR
+                    || *contract_addr == MOCK_BLUNA_TOKEN_CONTRACT_ADDR
+                {
+                    match from_binary(msg).unwrap() {
+                        Cw20QueryMsg::TokenInfo {} => {
+                            let token_inf: TokenInfoResponse =
+                                if *contract_addr == MOCK_STLUNA_TOKEN_CONTRACT_ADDR {
+                                    TokenInfoResponse {
+                                        name: "stluna".to_string(),
+                                        symbol: "stLUNA".to_string(),
+                                        decimals: 6,
+                                        total_supply: Uint128::new(10000u128),
+                                    }
+                                } else {
+                                    TokenInfoResponse {
+                                        name: "bluna".to_string(),
+                                        symbol: "bLUNA".to_string(),
+                                        decimals: 6,
+                                        total_supply: Uint128::new(10000u128),
+                                    }
+                                };
+                            SystemResult::Ok(ContractResult::Ok(to_binary(&token_inf).unwrap()))
+                        }
+                        Cw20QueryMsg::Balance { address } => {
+                            let balance = self
+                                .token_balances
+                                .get(contract_addr)
+                                .and_then(|balances| balances.get(&address))
+                                .cloned()
+                                .unwrap_or_default();
+                            SystemResult::Ok(ContractResult::Ok(
+                                to_binary(&BalanceResponse { balance }).unwrap(),
+                            ))
+                        }
+                        _ => unimplemented!(),
+                    }
                 } else if *contract_addr == MOCK_HUB_CONTRACT_ADDR {
                     match from_binary(msg).unwrap() {
                         basset::hub::QueryMsg::CurrentBatch {} => {
@@ -83,11 +109,11 @@ impl WasmMockQuerier {
                         }
                         basset::hub::QueryMsg::State {} => {
                             let state = StateResponse {
-                                bluna_exchange_rate: Decimal::from_str("0.95").unwrap(),
-                                stluna_exchange_rate: Decimal::from_str("1.5").unwrap(),
+                                bluna_exchange_rate: self.hub_bluna_exchange_rate,
+                                stluna_exchange_rate: self.hub_stluna_exchange_rate,
                                 total_bond_bluna_amount: Uint128::new(9500u128),
                                 total_bond_stluna_amount: Uint128::new(15000u128),
-                                last_index_modification: 0,
+                                last_index_modification: self.hub_last_index_modification,
                                 prev_hub_balance: Default::default(),
                                 last_unbonded_time: 0,
                                 last_processed_batch: 0,
@@ -104,7 +130,7 @@ impl WasmMockQuerier {
                                 peg_recovery_fee: Decimal::from_str("0.05").unwrap(),
                                 er_threshold: Decimal::from_str("1.0").unwrap(),
                                 reward_denom: "".to_string(),
-                                paused: None,
+                                paused: self.hub_paused,
                             };
                             SystemResult::Ok(ContractResult::from(to_binary(&params)))
                         }
@@ -121,6 +147,43 @@ impl WasmMockQuerier {
 
 impl WasmMockQuerier {
     pub fn new(base: MockQuerier<Empty>) -> Self {
-        WasmMockQuerier { base }
+        WasmMockQuerier {
+            base,
+            token_balances: HashMap::new(),
+            hub_paused: None,
+            hub_last_index_modification: 0,
+            hub_stluna_exchange_rate: Decimal::from_str("1.5").unwrap(),
+            hub_bluna_exchange_rate: Decimal::from_str("0.95").unwrap(),
+        }
+    }
+
+    /// Sets CW20 balances: (<TOKEN_ADDR>, [(<HOLDER_ADDR>, <BALANCE>)])
+    pub fn with_token_balances(&mut self, balances: &[(&str, &[(&str, Uint128)])]) {
+        for (token, token_balances) in balances.iter() {
+            let entry = self.token_balances.entry(token.to_string()).or_default();
+            for (holder, balance) in token_balances.iter() {
+                entry.insert(holder.to_string(), *balance);
+            }
+        }
+    }
+
+    /// Sets the Hub's paused parameter
+    pub fn with_hub_paused(&mut self, paused: Option<bool>) {
+        self.hub_paused = paused;
+    }
+
+    /// Sets the last time the Hub updated its exchange rates
+    pub fn with_hub_last_index_modification(&mut self, last_index_modification: u64) {
+        self.hub_last_index_modification = last_index_modification;
+    }
+
+    /// Sets the Hub's exchange rates
+    pub fn with_hub_exchange_rates(
+        &mut self,
+        stluna_exchange_rate: Decimal,
+        bluna_exchange_rate: Decimal,
+    ) {
+        self.hub_stluna_exchange_rate = stluna_exchange_rate;
+        self.hub_bluna_exchange_rate = bluna_exchange_rate;
     }
 }
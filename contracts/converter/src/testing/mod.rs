@@ -1,2 +1,3 @@
 mod mock_querier;
+mod simulation_tests;
 mod tests;
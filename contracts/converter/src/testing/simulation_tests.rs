@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright Lido
+
+use crate::error::ContractError;
+use crate::simulation::{
+    apply_converter_fee, get_required_bluna, get_required_return_before_fee, get_required_stluna,
+    simulate_bluna_to_stluna, simulate_luna_to_bluna, simulate_luna_to_stluna,
+    simulate_stluna_to_bluna, HubSnapshot,
+};
+use cosmwasm_std::{Decimal, Uint128};
+
+/// A tiny deterministic xorshift generator, so the property tests are reproducible
+/// without pulling in an extra dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn range(&mut self, lo: u128, hi: u128) -> u128 {
+        lo + (self.next() as u128) % (hi - lo + 1)
+    }
+}
+
+const CASES: usize = 10_000;
+
+/// Generates a Hub state consistent with the Hub's own accounting:
+/// the bLuna exchange rate is derived from the bonded amount and the supply.
+fn arbitrary_hub(rng: &mut Rng, peg_fee_active: bool) -> HubSnapshot {
+    let supply = rng.range(1_000_000, 1_000_000_000_000_000);
+    let bonded = if peg_fee_active {
+        supply * rng.range(800, 999) / 1000
+    } else {
+        supply * rng.range(1000, 1100) / 1000
+    };
+    HubSnapshot {
+        stluna_exchange_rate: Decimal::from_ratio(rng.range(1000, 2000), 1000u128),
+        bluna_exchange_rate: Decimal::from_ratio(bonded, supply),
+        er_threshold: Decimal::one(),
+        peg_recovery_fee: Decimal::permille(rng.range(0, 100) as u64),
+        total_bluna_supply: Uint128::from(supply),
+        total_bond_bluna_amount: Uint128::from(bonded),
+        requested_bluna_with_fee: Uint128::from(supply * rng.range(0, 50) / 1000),
+        paused: false,
+        last_index_modification: 0,
+    }
+}
+
+#[test]
+fn simulations_do_not_fail_on_consistent_hub_state() {
+    let mut rng = Rng(0x2545F4914F6CDD1D);
+    for i in 0..CASES {
+        let hub = arbitrary_hub(&mut rng, i % 2 == 0);
+        let amount = Uint128::from(rng.range(1, 1_000_000_000_000));
+        assert!(simulate_stluna_to_bluna(&hub, amount).is_ok(), "{:?}", hub);
+        assert!(simulate_bluna_to_stluna(&hub, amount).is_ok(), "{:?}", hub);
+        assert!(get_required_stluna(&hub, amount).is_ok(), "{:?}", hub);
+        assert!(get_required_bluna(&hub, amount).is_ok(), "{:?}", hub);
+    }
+}
+
+#[test]
+fn simulations_do_not_panic_on_arbitrary_hub_state() {
+    let mut rng = Rng(0x5851F42D4C957F2D);
+    for _ in 0..CASES {
+        let supply = rng.range(0, 1_000_000_000_000_000);
+        let hub = HubSnapshot {
+            stluna_exchange_rate: Decimal::from_ratio(rng.range(1, 3000), 1000u128),
+            bluna_exchange_rate: Decimal::from_ratio(rng.range(1, 2000), 1000u128),
+            er_threshold: Decimal::from_ratio(rng.range(0, 2000), 1000u128),
+            peg_recovery_fee: Decimal::permille(rng.range(0, 1500) as u64),
+            total_bluna_supply: Uint128::from(supply),
+            total_bond_bluna_amount: Uint128::from(rng.range(0, 1_000_000_000_000_000)),
+            requested_bluna_with_fee: Uint128::from(rng.range(0, supply)),
+            paused: false,
+            last_index_modification: 0,
+        };
+        let amount = Uint128::from(rng.range(0, 1_000_000_000_000));
+        let _ = simulate_stluna_to_bluna(&hub, amount);
+        let _ = simulate_bluna_to_stluna(&hub, amount);
+        let _ = get_required_stluna(&hub, amount);
+        let _ = get_required_bluna(&hub, amount);
+    }
+}
+
+#[test]
+fn spread_is_shortfall_against_fee_free_return() {
+    let mut rng = Rng(0x9E3779B97F4A7C15);
+    for i in 0..CASES {
+        let hub = arbitrary_hub(&mut rng, i % 2 == 0);
+        let amount = Uint128::from(rng.range(1, 1_000_000_000_000));
+        let fee_rate = Decimal::permille(rng.range(0, 10) as u64);
+        for simulation in [
+            simulate_stluna_to_bluna(&hub, amount).unwrap(),
+            simulate_bluna_to_stluna(&hub, amount).unwrap(),
+            simulate_luna_to_bluna(&hub, amount).unwrap(),
+            simulate_luna_to_stluna(&hub, amount).unwrap(),
+        ] {
+            let simulation = apply_converter_fee(simulation, fee_rate).unwrap();
+            assert_eq!(
+                simulation.return_amount + simulation.commission_amount + simulation.spread_amount,
+                simulation.fee_free_return_amount,
+                "{:?}",
+                hub
+            );
+        }
+    }
+}
+
+#[test]
+fn no_peg_fee_when_bonded_amount_covers_supply() {
+    // the bLuna exchange rate is below the threshold, but the bonded amount exceeds the supply
+    let hub = HubSnapshot {
+        stluna_exchange_rate: Decimal::from_ratio(3u128, 2u128),
+        bluna_exchange_rate: Decimal::from_ratio(102u128, 100u128),
+        er_threshold: Decimal::from_ratio(105u128, 100u128),
+        peg_recovery_fee: Decimal::permille(5),
+        total_bluna_supply: Uint128::from(10000u128),
+        total_bond_bluna_amount: Uint128::from(10200u128),
+        requested_bluna_with_fee: Uint128::zero(),
+        paused: false,
+        last_index_modification: 0,
+    };
+
+    let simulation = simulate_stluna_to_bluna(&hub, Uint128::from(100u128)).unwrap();
+    assert_eq!(simulation.required_peg_fee, Uint128::zero());
+    assert_eq!(simulation.peg_fee, Uint128::zero());
+    assert_eq!(simulation.return_amount, Uint128::from(147u128));
+    assert_eq!(
+        get_required_stluna(&hub, Uint128::from(147u128)).unwrap(),
+        Uint128::from(100u128)
+    );
+
+    let simulation = simulate_bluna_to_stluna(&hub, Uint128::from(147u128)).unwrap();
+    assert_eq!(simulation.required_peg_fee, Uint128::zero());
+    assert_eq!(simulation.peg_fee, Uint128::zero());
+    assert_eq!(simulation.return_amount, Uint128::from(99u128));
+    assert!(get_required_bluna(&hub, Uint128::from(99u128)).is_ok());
+}
+
+#[test]
+fn converter_fee() {
+    let mut rng = Rng(0x9E3779B97F4A7C15);
+    let hub = arbitrary_hub(&mut rng, true);
+    let simulation = simulate_stluna_to_bluna(&hub, Uint128::from(1000u128)).unwrap();
+    let (return_amount, commission_amount) =
+        (simulation.return_amount, simulation.commission_amount);
+
+    let simulation = apply_converter_fee(simulation, Decimal::permille(5)).unwrap();
+    assert_eq!(
+        simulation.converter_fee,
+        return_amount * Decimal::permille(5)
+    );
+    assert_eq!(
+        simulation.return_amount + simulation.converter_fee,
+        return_amount
+    );
+    assert_eq!(
+        simulation.commission_amount,
+        commission_amount + simulation.converter_fee
+    );
+
+    for _ in 0..CASES {
+        let fee_rate = Decimal::permille(rng.range(0, 100) as u64);
+        let asked = Uint128::from(rng.range(1, 1_000_000_000_000));
+        let required = get_required_return_before_fee(asked, fee_rate).unwrap();
+        assert!(required - required * fee_rate >= asked);
+        let below = required - Uint128::from(1u8);
+        assert!(below - below * fee_rate < asked);
+    }
+}
+
+#[test]
+fn simulation_underflow() {
+    let mut rng = Rng(0x2545F4914F6CDD1D);
+    let mut hub = arbitrary_hub(&mut rng, true);
+    hub.er_threshold = Decimal::from_ratio(2u128, 1u128);
+    hub.peg_recovery_fee = Decimal::from_ratio(3u128, 2u128);
+
+    let amount = Uint128::from(1000u128);
+    assert_eq!(
+        simulate_bluna_to_stluna(&hub, amount).err(),
+        Some(ContractError::SimulationUnderflow {})
+    );
+    assert_eq!(
+        get_required_stluna(&hub, amount),
+        Err(ContractError::SimulationUnderflow {})
+    );
+    assert_eq!(
+        get_required_bluna(&hub, amount),
+        Err(ContractError::SimulationUnderflow {})
+    );
+}
+
+/// Checks over fee-active and fee-inactive Hub states that **reverse** returns
+/// the minimal offer amount **simulate** converts to at least the asked amount
+fn assert_exact_reverse_simulation<S, R>(seed: u64, simulate: S, reverse: R)
+where
+    S: Fn(&HubSnapshot, Uint128) -> Uint128,
+    R: Fn(&HubSnapshot, Uint128) -> Uint128,
+{
+    let mut rng = Rng(seed);
+    for &peg_fee_active in &[false, true] {
+        for _ in 0..CASES {
+            let hub = arbitrary_hub(&mut rng, peg_fee_active);
+            let asked = Uint128::from(rng.range(1, 1_000_000_000_000));
+            let offer = reverse(&hub, asked);
+            assert!(simulate(&hub, offer) >= asked, "{:?} {}", hub, asked);
+            assert!(
+                simulate(&hub, offer - Uint128::from(1u8)) < asked,
+                "{:?} {}",
+                hub,
+                asked
+            );
+        }
+    }
+}
+
+/// Checks over fee-active and fee-inactive Hub states that the offer amount returned by **reverse**
+/// for the return amount of **simulate** is not bigger than the original offer and converts to
+/// the same return amount, i.e. the round-trip is within 1 unit of the return amount
+fn assert_round_trip<S, R>(seed: u64, simulate: S, reverse: R)
+where
+    S: Fn(&HubSnapshot, Uint128) -> Uint128,
+    R: Fn(&HubSnapshot, Uint128) -> Uint128,
+{
+    let mut rng = Rng(seed);
+    for &peg_fee_active in &[false, true] {
+        for _ in 0..CASES {
+            let hub = arbitrary_hub(&mut rng, peg_fee_active);
+            let offer = Uint128::from(rng.range(1, 1_000_000_000_000));
+            let return_amount = simulate(&hub, offer);
+            let required = reverse(&hub, return_amount);
+            assert!(required <= offer, "{:?} {}", hub, offer);
+            assert_eq!(
+                simulate(&hub, required),
+                return_amount,
+                "{:?} {}",
+                hub,
+                offer
+            );
+        }
+    }
+}
+
+#[test]
+fn stluna_to_bluna_round_trip() {
+    assert_round_trip(
+        0x2545F4914F6CDD1D,
+        |hub, amount| simulate_stluna_to_bluna(hub, amount).unwrap().return_amount,
+        |hub, amount| get_required_stluna(hub, amount).unwrap(),
+    );
+}
+
+#[test]
+fn bluna_to_stluna_round_trip() {
+    assert_round_trip(
+        0x5851F42D4C957F2D,
+        |hub, amount| simulate_bluna_to_stluna(hub, amount).unwrap().return_amount,
+        |hub, amount| get_required_bluna(hub, amount).unwrap(),
+    );
+}
+
+#[test]
+fn reverse_stluna_to_bluna_simulation() {
+    assert_exact_reverse_simulation(
+        0x9E3779B97F4A7C15,
+        |hub, amount| simulate_stluna_to_bluna(hub, amount).unwrap().return_amount,
+        |hub, amount| get_required_stluna(hub, amount).unwrap(),
+    );
+}
+
+#[test]
+fn reverse_bluna_to_stluna_simulation() {
+    assert_exact_reverse_simulation(
+        0xD1B54A32D192ED03,
+        |hub, amount| simulate_bluna_to_stluna(hub, amount).unwrap().return_amount,
+        |hub, amount| get_required_bluna(hub, amount).unwrap(),
+    );
+}
@@ -4,23 +4,42 @@
 
 use super::mock_querier::mock_dependencies as dependencies;
 use crate::contract::{
-    accumulate_prices, execute, instantiate, query_reverse_simulation, query_simulation,
+    accumulate_prices, execute, instantiate, migrate, query, query_config, query_rate_history,
+    query_reverse_simulation, query_simulation, query_twap, reply, RATE_HISTORY_PRUNE_LIMIT,
+    RATE_HISTORY_RETENTION,
+};
+use crate::error::ContractError;
+use crate::migration::{ConfigV100, CONFIG_V100, SWAP_REQUEST_V100};
+use crate::msgs::ExecuteMsg::{Receive, Swap};
+use crate::msgs::{
+    ConversionQuoteResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, PricingModelResponse,
+    QueryMsg, RateHistoryResponse, TwapResponse, UpdateConfigParams,
+};
+use crate::oracle::{
+    store_observation, OBSERVATIONS_CAPACITY, OBSERVATIONS_COVERAGE, OBSERVATION_INTERVAL,
+};
+use crate::queries::query_hub_snapshot;
+use crate::simulation::HubSnapshot;
+use crate::state::{
+    AccumulatorMode, Config, NativeSwapMode, Observation, ReserveModel, CONFIG, OBSERVATIONS_COUNT,
 };
-use crate::msgs::InstantiateMsg;
-use crate::state::Config;
 use crate::testing::mock_querier::{
-    MOCK_BLUNA_TOKEN_CONTRACT_ADDR, MOCK_HUB_CONTRACT_ADDR, MOCK_STLUNA_TOKEN_CONTRACT_ADDR,
+    WasmMockQuerier, MOCK_BLUNA_TOKEN_CONTRACT_ADDR, MOCK_HUB_CONTRACT_ADDR,
+    MOCK_STLUNA_TOKEN_CONTRACT_ADDR,
 };
-use astroport::asset::{Asset, AssetInfo};
-use astroport::pair::ExecuteMsg::Receive;
-use astroport::pair::TWAP_PRECISION;
-use cosmwasm_std::testing::{mock_env, mock_info};
+use astroport::asset::{Asset, AssetInfo, PairInfo};
+use astroport::factory::PairType;
+use astroport::pair::{CumulativePricesResponse, MigrateMsg, PoolResponse, TWAP_PRECISION};
+use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    to_binary, Addr, Api, BlockInfo, CosmosMsg, Env, OwnedDeps, Querier, Storage, Timestamp,
-    Uint128, WasmMsg,
+    attr, from_binary, to_binary, Addr, Api, BankMsg, BlockInfo, Coin, ContractResult, CosmosMsg,
+    Decimal, Deps, Env, Event, OwnedDeps, Querier, Reply, StdError, Storage,
+    SubMsgExecutionResponse, Timestamp, Uint128, WasmMsg,
 };
+use cw2::{get_contract_version, set_contract_version};
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
 use std::borrow::BorrowMut;
+use std::str::FromStr;
 
 pub fn initialize<S: Storage, A: Api, Q: Querier>(deps: &mut OwnedDeps<S, A, Q>) {
     let msg = InstantiateMsg {
@@ -124,188 +143,2168 @@ fn proper_swap_bluna_stluna() {
 }
 
 #[test]
-fn proper_simulation_query() {
+fn proper_swap_with_allowance() {
     let mut deps = dependencies(&[]);
 
     initialize(deps.borrow_mut());
 
-    let bluna_amount = Uint128::from(150u128);
-    let expected_return_stluna_amount = Uint128::from(90u128);
-    let simulation_response = query_simulation(
-        deps.as_ref(),
-        Asset {
+    let sender = "addr";
+    let amount = Uint128::from(100u128);
+
+    let swap = Swap {
+        offer_asset: Asset {
             info: AssetInfo::Token {
-                contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+                contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
             },
-            amount: bluna_amount,
+            amount,
         },
-    )
-    .unwrap();
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), swap).unwrap();
+    assert_eq!(2, res.messages.len());
+
     assert_eq!(
-        expected_return_stluna_amount,
-        simulation_response.return_amount
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_STLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: sender.to_string(),
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount,
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_STLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: MOCK_HUB_CONTRACT_ADDR.to_string(),
+                amount,
+                msg: to_binary(&basset::hub::Cw20HookMsg::Convert {}).unwrap()
+            })
+            .unwrap(),
+            funds: vec![],
+        })
     );
 
-    let stluna_amount = Uint128::from(100u128);
-    let expected_return_bluna_amount = Uint128::from(150u128);
-    let simulation_response = query_simulation(
-        deps.as_ref(),
-        Asset {
+    // only stLuna and bLuna tokens can be converted
+    let swap = Swap {
+        offer_asset: Asset {
             info: AssetInfo::Token {
-                contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+                contract_addr: Addr::unchecked("other_token"),
             },
-            amount: stluna_amount,
+            amount,
         },
-    )
-    .unwrap();
-    assert_eq!(
-        expected_return_bluna_amount,
-        simulation_response.return_amount
-    )
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), swap).unwrap_err();
+    assert_eq!(err, ContractError::AssetMismatch {});
 }
 
 #[test]
-fn proper_reverse_simulation_query() {
+fn proper_swap_native_luna() {
     let mut deps = dependencies(&[]);
 
     initialize(deps.borrow_mut());
 
-    let bluna_amount = Uint128::from(150u128);
-    let expected_offer_stluna_amount = Uint128::from(100u128);
-    let simulation_response = query_reverse_simulation(
-        deps.as_ref(),
-        Asset {
-            info: AssetInfo::Token {
-                contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+    let sender = "addr";
+    let amount = Uint128::from(100u128);
+    let funds = [Coin {
+        denom: "uluna".to_string(),
+        amount,
+    }];
+    let swap = Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
             },
-            amount: bluna_amount,
+            amount,
         },
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+
+    // native swaps are disabled by default
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(sender, &funds),
+        swap.clone(),
     )
-    .unwrap();
-    assert_eq!(
-        expected_offer_stluna_amount,
-        simulation_response.offer_amount
-    );
+    .unwrap_err();
+    assert_eq!(err, ContractError::NonSupported {});
 
-    let stluna_amount = Uint128::from(90u128);
-    let expected_offer_bluna_amount = Uint128::from(149u128); // ~150
-    let simulation_response = query_reverse_simulation(
-        deps.as_ref(),
-        Asset {
+    // 100 Luna is bonded for 100 bLuna (with the peg recovery fee) or 66 stLuna
+    for (mode, ask_token, bond_msg, return_amount) in [
+        (
+            NativeSwapMode::Bluna,
+            MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+            basset::hub::ExecuteMsg::Bond {},
+            Uint128::from(100u128),
+        ),
+        (
+            NativeSwapMode::Stluna,
+            MOCK_STLUNA_TOKEN_CONTRACT_ADDR,
+            basset::hub::ExecuteMsg::BondForStLuna {},
+            Uint128::from(66u128),
+        ),
+    ] {
+        let msg = update_config_msg(UpdateConfigParams {
+            native_swap_mode: Some(mode),
+            ..Default::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(sender, &[]),
+            swap.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Std(StdError::generic_err(
+                "Native token balance mismatch between the argument and the transferred"
+            ))
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(sender, &funds),
+            swap.clone(),
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_HUB_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&bond_msg).unwrap(),
+                funds: funds.to_vec(),
+            })
+        );
+
+        // the minted tokens are sent to the sender
+        deps.querier
+            .with_token_balances(&[(ask_token, &[(MOCK_CONTRACT_ADDR, return_amount)])]);
+        let swap_reply = Reply {
+            id: res.messages[0].id,
+            result: ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        let res = reply(deps.as_mut(), mock_env(), swap_reply).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: ask_token.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: sender.to_string(),
+                    amount: return_amount,
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+        assert!(res.attributes.contains(&attr("offer_asset", "uluna")));
+        deps.querier
+            .with_token_balances(&[(ask_token, &[(MOCK_CONTRACT_ADDR, Uint128::zero())])]);
+    }
+
+    // only LUNA can be bonded
+    let swap = Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount,
+        },
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let funds = [Coin {
+        denom: "uusd".to_string(),
+        amount,
+    }];
+    let err = execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), swap).unwrap_err();
+    assert_eq!(err, ContractError::NonSupported {});
+}
+
+#[test]
+fn swap_with_unexpected_funds() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let sender = "addr";
+    let amount = Uint128::from(100u128);
+
+    // only the offered native coin can be sent
+    let swap = Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            amount,
+        },
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let funds = [
+        Coin {
+            denom: "uluna".to_string(),
+            amount,
+        },
+        Coin {
+            denom: "uusd".to_string(),
+            amount,
+        },
+    ];
+    let err = execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), swap).unwrap_err();
+    assert_eq!(err, ContractError::UnexpectedFunds {});
+
+    // no coins can be sent with a token offer
+    let funds = [Coin {
+        denom: "uluna".to_string(),
+        amount,
+    }];
+    let swap = Swap {
+        offer_asset: Asset {
             info: AssetInfo::Token {
                 contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
             },
-            amount: stluna_amount,
+            amount,
         },
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), swap).unwrap_err();
+    assert_eq!(err, ContractError::UnexpectedFunds {});
+
+    let swap = astroport::pair::Cw20HookMsg::Swap {
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &funds),
+        receive,
     )
-    .unwrap();
-    assert_eq!(
-        expected_offer_bluna_amount,
-        simulation_response.offer_amount
-    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::UnexpectedFunds {});
 }
 
 #[test]
-fn test_accumulate_prices() {
-    struct Case {
-        block_time: u64,
-        block_time_last: u64,
-        last0: u128,
-        last1: u128,
-    }
+fn swap_belief_price_assertion() {
+    let mut deps = dependencies(&[]);
 
-    struct Result {
-        block_time_last: u64,
-        price_x: u128,
-        price_y: u128,
-        is_some: bool,
-    }
+    initialize(deps.borrow_mut());
 
-    let price_precision = 10u128.pow(TWAP_PRECISION.into());
+    let sender = "addr";
+    let amount = Uint128::from(100u128);
 
-    let test_cases: Vec<(Case, Result)> = vec![
-        (
-            Case {
-                block_time: 1000,
-                block_time_last: 0,
-                last0: 0,
-                last1: 0,
-            },
-            Result {
-                block_time_last: 1000,
-                price_x: 1500,
-                price_y: 633,
-                is_some: true,
-            },
-        ),
-        // Same block height, no changes
-        (
-            Case {
-                block_time: 1000,
-                block_time_last: 1000,
-                last0: price_precision,
-                last1: 2 * price_precision,
-            },
-            Result {
-                block_time_last: 1000,
-                price_x: 1,
-                price_y: 2,
-                is_some: false,
-            },
-        ),
-        (
-            Case {
-                block_time: 1500,
-                block_time_last: 1000,
-                last0: 1500 * price_precision,
-                last1: 633 * price_precision,
-            },
-            Result {
-                block_time_last: 1500,
-                price_x: 2250,
-                price_y: 949,
-                is_some: true,
-            },
-        ),
-    ];
+    // 100 stLuna is converted into 150 bLuna, but the user expects 200 bLuna
+    let swap = astroport::pair::Cw20HookMsg::Swap {
+        belief_price: Some(Decimal::from_str("0.5").unwrap()),
+        max_spread: Some(Decimal::from_str("0.01").unwrap()),
+        to: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    let stluna_info = mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]);
+    let err = execute(deps.as_mut(), mock_env(), stluna_info.clone(), receive).unwrap_err();
+    assert_eq!(err, ContractError::MaxSpreadAssertion {});
 
-    let deps = dependencies(&[]);
+    let swap = astroport::pair::Cw20HookMsg::Swap {
+        belief_price: Some(Decimal::from_str("0.5").unwrap()),
+        max_spread: Some(Decimal::from_str("0.6").unwrap()),
+        to: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), stluna_info.clone(), receive).unwrap_err();
+    assert_eq!(err, ContractError::AllowedSpreadAssertion {});
 
-    for test_case in test_cases {
-        let (case, result) = test_case;
+    let swap = astroport::pair::Cw20HookMsg::Swap {
+        belief_price: Some(Decimal::zero()),
+        max_spread: Some(Decimal::from_str("0.01").unwrap()),
+        to: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), stluna_info.clone(), receive).unwrap_err();
+    assert_eq!(err, ContractError::InvalidBeliefPrice {});
 
-        let env = mock_env_with_block_time(case.block_time);
-        let config = accumulate_prices(
-            deps.as_ref(),
-            env,
-            &Config {
-                block_time_last: case.block_time_last,
-                price0_cumulative_last: Uint128::new(case.last0),
-                price1_cumulative_last: Uint128::new(case.last1),
-                hub_addr: Addr::unchecked(MOCK_HUB_CONTRACT_ADDR),
-                stluna_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
-                bluna_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
-                owner: Addr::unchecked("owner"),
-            },
-        )
-        .unwrap();
+    let swap = astroport::pair::Cw20HookMsg::Swap {
+        belief_price: Some(Decimal::from_str("0.67").unwrap()),
+        max_spread: Some(Decimal::from_str("0.01").unwrap()),
+        to: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    execute(deps.as_mut(), mock_env(), stluna_info, receive).unwrap();
+}
 
-        assert_eq!(result.is_some, config.is_some());
+#[test]
+fn reply_max_spread_assertion() {
+    let mut deps = dependencies(&[]);
 
-        if let Some(config) = config {
-            assert_eq!(config.2, result.block_time_last);
-            assert_eq!(
-                config.0 / Uint128::from(price_precision),
-                Uint128::new(result.price_x)
-            );
-            assert_eq!(
-                config.1 / Uint128::from(price_precision),
+    initialize(deps.borrow_mut());
+
+    let sender = "addr";
+    let amount = Uint128::from(100u128);
+
+    let stluna_info = mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]);
+    let swap = astroport::pair::Cw20HookMsg::Swap {
+        belief_price: None,
+        max_spread: Some(Decimal::from_str("0.01").unwrap()),
+        to: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    execute(deps.as_mut(), mock_env(), stluna_info, receive).unwrap();
+
+    let swap_reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+
+    // the Hub minted 140 bLuna instead of simulated 150 bLuna
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(140u128))],
+    )]);
+    let err = reply(deps.as_mut(), mock_env(), swap_reply.clone()).unwrap_err();
+    assert_eq!(err, ContractError::MaxSpreadAssertion {});
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(149u128))],
+    )]);
+    let res = reply(deps.as_mut(), mock_env(), swap_reply).unwrap();
+    assert_eq!(1, res.messages.len());
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_BLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: sender.to_string(),
+                amount: Uint128::from(149u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}
+
+#[test]
+fn reply_minimum_receive_assertion() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let sender = "addr";
+    let amount = Uint128::from(100u128);
+
+    let stluna_info = mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]);
+    let swap = Cw20HookMsg::Swap {
+        belief_price: None,
+        max_spread: Some(Decimal::from_str("0.01").unwrap()),
+        to: None,
+        minimum_receive: Some(Uint128::from(150u128)),
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount,
+        msg: to_binary(&swap).unwrap(),
+    });
+    execute(deps.as_mut(), mock_env(), stluna_info, receive).unwrap();
+
+    let swap_reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(149u128))],
+    )]);
+    let err = reply(deps.as_mut(), mock_env(), swap_reply.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::MinimumReceiveAssertion {
+            minimum_receive: Uint128::from(150u128),
+            return_amount: Uint128::from(149u128),
+        }
+    );
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(150u128))],
+    )]);
+    let res = reply(deps.as_mut(), mock_env(), swap_reply).unwrap();
+    assert_eq!(1, res.messages.len());
+}
+
+#[test]
+fn update_config() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let msg = update_config_msg(UpdateConfigParams {
+        hub_address: Some("new_hub".to_string()),
+        bluna_address: Some("new_bluna".to_string()),
+        native_swap_mode: Some(NativeSwapMode::Stluna),
+        ..Default::default()
+    });
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_config"),
+            attr("hub_address", "new_hub"),
+            attr("bluna_address", "new_bluna"),
+            attr("native_swap_mode", "stluna"),
+        ]
+    );
+
+    let config = query_config(deps.as_ref()).unwrap();
+    assert_eq!(config.hub_address, Addr::unchecked("new_hub"));
+    assert_eq!(
+        config.stluna_address,
+        Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR)
+    );
+    assert_eq!(config.bluna_address, Addr::unchecked("new_bluna"));
+    assert_eq!(config.native_swap_mode, NativeSwapMode::Stluna);
+
+    let msg = update_config_msg(UpdateConfigParams {
+        stluna_address: Some("new_bluna".to_string()),
+        ..Default::default()
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::DoublingAssets {});
+}
+
+#[test]
+fn change_ownership() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let new_owner = "new_owner";
+    let propose_msg = ExecuteMsg::ProposeNewOwner {
+        owner: new_owner.to_string(),
+        expires_in: 100,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(new_owner, &[]),
+        propose_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        ExecuteMsg::ProposeNewOwner {
+            owner: new_owner.to_string(),
+            expires_in: u64::MAX,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::OwnershipProposalTtlTooLong { max_ttl: 1209600 }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(new_owner, &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::OwnershipProposalNotFound {});
+
+    let env = mock_env();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        propose_msg.clone(),
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr", &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env_with_block_time(env.block.time.seconds() + 101),
+        mock_info(new_owner, &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::OwnershipProposalExpired {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::DropOwnershipProposal {},
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(new_owner, &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::OwnershipProposalNotFound {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        propose_msg,
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info(new_owner, &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_ownership"),
+            attr("new_owner", new_owner),
+        ]
+    );
+
+    let config = query_config(deps.as_ref()).unwrap();
+    assert_eq!(config.owner, Addr::unchecked(new_owner));
+}
+
+#[test]
+fn reply_sends_balance_delta() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    // someone has sent bLuna tokens directly to the contract
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(10u128))],
+    )]);
+
+    let sender = "addr";
+    let swap = Cw20HookMsg::Swap {
+        belief_price: None,
+        max_spread: None,
+        to: None,
+        minimum_receive: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&swap).unwrap(),
+    });
+    let stluna_info = mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]);
+    execute(deps.as_mut(), mock_env(), stluna_info, receive).unwrap();
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(160u128))],
+    )]);
+    let swap_reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), swap_reply).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_BLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: sender.to_string(),
+                amount: Uint128::from(150u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "swap"),
+            attr("sender", sender),
+            attr("receiver", sender),
+            attr("offer_asset", MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+            attr("ask_asset", MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+            attr("offer_amount", "100"),
+            attr("return_amount", "150"),
+            attr("spread_amount", "0"),
+            attr("commission_amount", "7"),
+            attr("peg_fee_amount", "7"),
+            attr("converter_fee_amount", "0"),
+            attr("stluna_exchange_rate", "1.5"),
+            attr("bluna_exchange_rate", "0.95"),
+        ]
+    );
+}
+
+#[test]
+fn converter_fee() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let update_fee = |fee_rate: &str, fee_collector: Option<&str>| {
+        update_config_msg(UpdateConfigParams {
+            fee_rate: Some(Decimal::from_str(fee_rate).unwrap()),
+            fee_collector: fee_collector.map(|addr| addr.to_string()),
+            ..Default::default()
+        })
+    };
+    let owner_info = mock_info("owner", &[]);
+
+    let msg = update_fee("0.011", Some("collector"));
+    let err = execute(deps.as_mut(), mock_env(), owner_info.clone(), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeeRateTooHigh {
+            max_fee_rate: Decimal::from_str("0.01").unwrap()
+        }
+    );
+
+    let msg = update_fee("0.01", None);
+    let err = execute(deps.as_mut(), mock_env(), owner_info.clone(), msg).unwrap_err();
+    assert_eq!(err, ContractError::FeeCollectorNotSet {});
+
+    let msg = update_fee("0.01", Some("collector"));
+    execute(deps.as_mut(), mock_env(), owner_info, msg).unwrap();
+
+    // 100 stLuna is converted into 150 bLuna, 1 bLuna of which is the converter fee
+    let stluna = Asset {
+        info: AssetInfo::Token {
+            contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+        },
+        amount: Uint128::from(100u128),
+    };
+    let simulation_response = query_simulation(deps.as_ref(), stluna).unwrap();
+    assert_eq!(Uint128::from(149u128), simulation_response.return_amount);
+    assert_eq!(Uint128::from(8u128), simulation_response.commission_amount);
+
+    let bluna = Asset {
+        info: AssetInfo::Token {
+            contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+        },
+        amount: Uint128::from(149u128),
+    };
+    let simulation_response = query_reverse_simulation(deps.as_ref(), bluna).unwrap();
+    assert_eq!(Uint128::from(100u128), simulation_response.offer_amount);
+    assert_eq!(Uint128::from(8u128), simulation_response.commission_amount);
+
+    let sender = "addr";
+    let swap = Cw20HookMsg::Swap {
+        belief_price: None,
+        max_spread: None,
+        to: None,
+        minimum_receive: None,
+    };
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&swap).unwrap(),
+    });
+    let stluna_info = mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]);
+    execute(deps.as_mut(), mock_env(), stluna_info, receive).unwrap();
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(150u128))],
+    )]);
+    let swap_reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), swap_reply).unwrap();
+    assert_eq!(2, res.messages.len());
+    for (message, (recipient, amount)) in res
+        .messages
+        .iter()
+        .zip([(sender, 149u128), ("collector", 1u128)])
+    {
+        assert_eq!(
+            message.msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_BLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: Uint128::from(amount),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+    }
+    assert!(res.attributes.contains(&attr("return_amount", "149")));
+    assert!(res.attributes.contains(&attr("commission_amount", "8")));
+    assert!(res.attributes.contains(&attr("converter_fee_amount", "1")));
+}
+
+#[test]
+fn pause() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let msg = update_config_msg(UpdateConfigParams {
+        guardian: Some("guardian".to_string()),
+        ..Default::default()
+    });
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr", &[]),
+        ExecuteMsg::Pause {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("guardian", &[]),
+        ExecuteMsg::Pause {},
+    )
+    .unwrap();
+    assert_eq!(res.attributes, vec![attr("action", "pause")]);
+    assert!(query_config(deps.as_ref()).unwrap().paused);
+
+    let receive = Receive(Cw20ReceiveMsg {
+        sender: "addr".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::Swap {
+            belief_price: None,
+            max_spread: None,
+            to: None,
+            minimum_receive: None,
+        })
+        .unwrap(),
+    });
+    let stluna_info = mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        stluna_info.clone(),
+        receive.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Paused {});
+
+    // queries keep working
+    let stluna = Asset {
+        info: AssetInfo::Token {
+            contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+        },
+        amount: Uint128::from(100u128),
+    };
+    query_simulation(deps.as_ref(), stluna).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap();
+    assert_eq!(res.attributes, vec![attr("action", "unpause")]);
+
+    // swaps fail fast while the Hub is paused
+    deps.querier.with_hub_paused(Some(true));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        stluna_info.clone(),
+        receive.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::HubPaused {});
+
+    deps.querier.with_hub_paused(Some(false));
+    execute(deps.as_mut(), mock_env(), stluna_info, receive).unwrap();
+}
+
+#[test]
+fn migration() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+    let version = get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(version.contract, "lido-terra-stluna-bluna-converter");
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+    // v1.0.0 did not store its version
+    let mut deps = dependencies(&[]);
+    let legacy_config = ConfigV100 {
+        block_time_last: 10,
+        price0_cumulative_last: Uint128::from(1u128),
+        price1_cumulative_last: Uint128::from(2u128),
+        hub_addr: Addr::unchecked(MOCK_HUB_CONTRACT_ADDR),
+        stluna_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+        bluna_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+        owner: Addr::unchecked("owner"),
+    };
+    CONFIG_V100
+        .save(deps.as_mut().storage, &legacy_config)
+        .unwrap();
+    SWAP_REQUEST_V100
+        .save(
+            deps.as_mut().storage,
+            &(Addr::unchecked("addr"), Addr::unchecked("token")),
+        )
+        .unwrap();
+
+    let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "migrate"),
+            attr("previous_contract_version", "1.0.0"),
+            attr("new_contract_version", env!("CARGO_PKG_VERSION")),
+        ]
+    );
+
+    let config = query_config(deps.as_ref()).unwrap();
+    assert_eq!(config.owner, Addr::unchecked("owner"));
+    assert_eq!(config.native_swap_mode, NativeSwapMode::Disabled);
+    assert_eq!(config.fee_rate, Decimal::zero());
+    assert_eq!(config.fee_collector, None);
+    assert_eq!(config.guardian, None);
+    assert!(!config.paused);
+    assert_eq!(
+        SWAP_REQUEST_V100.may_load(deps.as_ref().storage).unwrap(),
+        None
+    );
+    let version = get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+    // migrating to the same version keeps the state
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    assert_eq!(query_config(deps.as_ref()).unwrap(), config);
+
+    set_contract_version(
+        deps.as_mut().storage,
+        "lido-terra-stluna-bluna-converter",
+        "99.0.0",
+    )
+    .unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::MigrationDowngrade {
+            stored_version: "99.0.0".to_string(),
+            new_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    );
+
+    set_contract_version(deps.as_mut().storage, "other-contract", "1.0.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidContractName {
+            contract: "other-contract".to_string(),
+        }
+    );
+
+    // an unreadable version record is not mistaken for v1.0.0
+    deps.storage.set(b"contract_info", b"corrupted");
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    match err {
+        ContractError::Std(StdError::ParseErr { .. }) => {}
+        _ => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn multiple_pending_swaps() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let amount = Uint128::from(100u128);
+
+    // stLuna -> bLuna swap for "addr0000" and bLuna -> stLuna swap for "addr0001" in one transaction
+    let mut reply_ids = vec![];
+    for (sender, token) in [
+        ("addr0000", MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+        ("addr0001", MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+    ] {
+        let swap = Cw20HookMsg::Swap {
+            belief_price: None,
+            max_spread: Some(Decimal::from_str("0.5").unwrap()),
+            to: None,
+            minimum_receive: None,
+        };
+        let receive = Receive(Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount,
+            msg: to_binary(&swap).unwrap(),
+        });
+        let res = execute(deps.as_mut(), mock_env(), mock_info(token, &[]), receive).unwrap();
+        reply_ids.push(res.messages[0].id);
+    }
+    assert_eq!(reply_ids, vec![1, 2]);
+
+    deps.querier.with_token_balances(&[(
+        MOCK_STLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(63u128))],
+    )]);
+    let swap_reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), swap_reply.clone()).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_STLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0001".to_string(),
+                amount: Uint128::from(63u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    // the pending swap is removed after the reply
+    reply(deps.as_mut(), mock_env(), swap_reply).unwrap_err();
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(150u128))],
+    )]);
+    let swap_reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), swap_reply).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_BLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0000".to_string(),
+                amount: Uint128::from(150u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}
+
+#[test]
+fn sweep() {
+    let mut deps = dependencies(&[Coin {
+        denom: "uluna".to_string(),
+        amount: Uint128::from(20u128),
+    }]);
+
+    initialize(deps.borrow_mut());
+
+    deps.querier.with_token_balances(&[(
+        MOCK_BLUNA_TOKEN_CONTRACT_ADDR,
+        &[(MOCK_CONTRACT_ADDR, Uint128::from(10u128))],
+    )]);
+
+    let bluna_info = AssetInfo::Token {
+        contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+    };
+    let msg = ExecuteMsg::Sweep {
+        asset_info: bluna_info.clone(),
+        recipient: "treasury".to_string(),
+        amount: None,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_BLUNA_TOKEN_CONTRACT_ADDR.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "treasury".to_string(),
+                amount: Uint128::from(10u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Sweep {
+            asset_info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            recipient: "treasury".to_string(),
+            amount: Some(Uint128::from(5u128)),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "treasury".to_string(),
+            amount: vec![Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(5u128),
+            }],
+        })
+    );
+}
+
+#[test]
+fn proper_simulation_query() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let bluna_amount = Uint128::from(150u128);
+    let expected_return_stluna_amount = Uint128::from(90u128);
+    let simulation_response = query_simulation(
+        deps.as_ref(),
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+            },
+            amount: bluna_amount,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        expected_return_stluna_amount,
+        simulation_response.return_amount
+    );
+    // the peg recovery fee is 7 bLuna ~ 4 stLuna
+    assert_eq!(Uint128::from(4u128), simulation_response.commission_amount);
+    assert_eq!(Uint128::zero(), simulation_response.spread_amount);
+
+    let stluna_amount = Uint128::from(100u128);
+    let expected_return_bluna_amount = Uint128::from(150u128);
+    let simulation_response = query_simulation(
+        deps.as_ref(),
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+            },
+            amount: stluna_amount,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        expected_return_bluna_amount,
+        simulation_response.return_amount
+    );
+    assert_eq!(Uint128::from(7u128), simulation_response.commission_amount);
+    assert_eq!(Uint128::zero(), simulation_response.spread_amount);
+
+    // native LUNA is simulated with the Hub's bond rate
+    let luna = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uluna".to_string(),
+        },
+        amount: Uint128::from(100u128),
+    };
+    let err = query_simulation(deps.as_ref(), luna.clone()).unwrap_err();
+    assert_eq!(err, StdError::generic_err("invalid offer asset"));
+
+    for (mode, return_amount, commission_amount) in [
+        (NativeSwapMode::Bluna, 100u128, 5u128),
+        (NativeSwapMode::Stluna, 66u128, 0u128),
+    ] {
+        let msg = update_config_msg(UpdateConfigParams {
+            native_swap_mode: Some(mode),
+            ..Default::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        let simulation_response = query_simulation(deps.as_ref(), luna.clone()).unwrap();
+        assert_eq!(
+            Uint128::from(return_amount),
+            simulation_response.return_amount
+        );
+        assert_eq!(
+            Uint128::from(commission_amount),
+            simulation_response.commission_amount
+        );
+    }
+}
+
+#[test]
+fn proper_reverse_simulation_query() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let bluna_amount = Uint128::from(150u128);
+    let expected_offer_stluna_amount = Uint128::from(100u128);
+    let simulation_response = query_reverse_simulation(
+        deps.as_ref(),
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+            },
+            amount: bluna_amount,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        expected_offer_stluna_amount,
+        simulation_response.offer_amount
+    );
+    assert_eq!(Uint128::from(7u128), simulation_response.commission_amount);
+
+    let stluna_amount = Uint128::from(90u128);
+    let expected_offer_bluna_amount = Uint128::from(150u128);
+    let simulation_response = query_reverse_simulation(
+        deps.as_ref(),
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+            },
+            amount: stluna_amount,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        expected_offer_bluna_amount,
+        simulation_response.offer_amount
+    );
+    assert_eq!(Uint128::from(4u128), simulation_response.commission_amount);
+}
+
+#[test]
+fn proper_conversion_quote_query() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let offer_asset = Asset {
+        info: AssetInfo::Token {
+            contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+        },
+        amount: Uint128::from(100u128),
+    };
+    let res: ConversionQuoteResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ConversionQuote {
+                offer_asset: offer_asset.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        res,
+        ConversionQuoteResponse {
+            offer_asset,
+            ask_asset: Asset {
+                info: AssetInfo::Token {
+                    contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+                },
+                amount: Uint128::from(150u128),
+            },
+            stluna_exchange_rate: Decimal::from_str("1.5").unwrap(),
+            bluna_exchange_rate: Decimal::from_str("0.95").unwrap(),
+            er_threshold: Decimal::one(),
+            peg_fee_active: true,
+            max_peg_fee: Uint128::from(7u128),
+            required_peg_fee: Uint128::from(507u128),
+            peg_fee: Uint128::from(7u128),
+            converter_fee: Uint128::zero(),
+            commission_amount: Uint128::from(7u128),
+            spread_amount: Uint128::zero(),
+            effective_price: Decimal::from_ratio(100u128, 150u128),
+        }
+    );
+
+    let err = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::ConversionQuote {
+            offer_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uluna".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, StdError::generic_err("invalid offer asset"));
+}
+
+#[test]
+fn pair_and_pricing_model_query() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let res: PairInfo =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Pair {}).unwrap()).unwrap();
+    assert_eq!(
+        res.pair_type,
+        PairType::Custom("lido-converter".to_string())
+    );
+    assert_eq!(
+        res.asset_infos,
+        [
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+            },
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+            },
+        ]
+    );
+
+    let res: PricingModelResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::PricingModel {}).unwrap()).unwrap();
+    assert_eq!(res.model, "lido-converter");
+    assert_eq!(res.stluna_exchange_rate, Decimal::from_str("1.5").unwrap());
+    assert_eq!(res.bluna_exchange_rate, Decimal::from_str("0.95").unwrap());
+    assert_eq!(
+        res.stluna_price,
+        Decimal::from_str("1.578947368421052631").unwrap()
+    );
+    assert_eq!(
+        res.bluna_price,
+        Decimal::from_str("0.633333333333333333").unwrap()
+    );
+    assert_eq!(res.er_threshold, Decimal::one());
+    assert_eq!(res.peg_recovery_fee, Decimal::from_str("0.05").unwrap());
+    assert!(res.peg_fee_active);
+    assert_eq!(res.fee_rate, Decimal::zero());
+}
+
+#[test]
+fn pool_query() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    // 9500 LUNA bonded for bLuna are worth 6333 stLuna (rate 1.5) and 10000 bLuna (rate 0.95),
+    // so the constant product price of stLuna is 1.579 bLuna
+    let res: PoolResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Pool {}).unwrap()).unwrap();
+    assert_eq!(res.assets[0].amount, Uint128::new(6333));
+    assert_eq!(res.assets[1].amount, Uint128::new(10000));
+    assert_eq!(res.total_share, Uint128::zero());
+
+    let res: CumulativePricesResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_with_block_time(1000),
+            QueryMsg::CumulativePrices {},
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.assets[0].amount, Uint128::new(6333));
+    assert_eq!(res.assets[1].amount, Uint128::new(10000));
+
+    let msg = update_config_msg(UpdateConfigParams {
+        reserve_model: Some(ReserveModel::TotalSupply),
+        ..Default::default()
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_config"),
+            attr("reserve_model", "total_supply")
+        ]
+    );
+
+    let res: PoolResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Pool {}).unwrap()).unwrap();
+    assert_eq!(res.assets[0].amount, Uint128::new(10000));
+    assert_eq!(res.assets[1].amount, Uint128::new(10000));
+}
+
+#[test]
+fn test_accumulate_prices() {
+    struct Case {
+        block_time: u64,
+        block_time_last: u64,
+        last0: u128,
+        last1: u128,
+    }
+
+    struct Result {
+        block_time_last: u64,
+        price_x: u128,
+        price_y: u128,
+        is_some: bool,
+    }
+
+    let price_precision = 10u128.pow(TWAP_PRECISION.into());
+
+    let test_cases: Vec<(Case, Result)> = vec![
+        (
+            Case {
+                block_time: 1000,
+                block_time_last: 0,
+                last0: 0,
+                last1: 0,
+            },
+            Result {
+                block_time_last: 1000,
+                price_x: 1500,
+                price_y: 633,
+                is_some: true,
+            },
+        ),
+        // Same block height, no changes
+        (
+            Case {
+                block_time: 1000,
+                block_time_last: 1000,
+                last0: price_precision,
+                last1: 2 * price_precision,
+            },
+            Result {
+                block_time_last: 1000,
+                price_x: 1,
+                price_y: 2,
+                is_some: false,
+            },
+        ),
+        (
+            Case {
+                block_time: 1500,
+                block_time_last: 1000,
+                last0: 1500 * price_precision,
+                last1: 633 * price_precision,
+            },
+            Result {
+                block_time_last: 1500,
+                price_x: 2250,
+                price_y: 949,
+                is_some: true,
+            },
+        ),
+    ];
+
+    let deps = dependencies(&[]);
+    let hub = hub_snapshot(deps.as_ref());
+
+    for test_case in test_cases {
+        let (case, result) = test_case;
+
+        let env = mock_env_with_block_time(case.block_time);
+        let config = accumulate_prices(
+            env,
+            &Config {
+                block_time_last: case.block_time_last,
+                price0_cumulative_last: Uint128::new(case.last0),
+                price1_cumulative_last: Uint128::new(case.last1),
+                price0_last: Uint128::zero(),
+                price1_last: Uint128::zero(),
+                accumulator_mode: AccumulatorMode::BlockTime,
+                hub_addr: Addr::unchecked(MOCK_HUB_CONTRACT_ADDR),
+                stluna_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+                bluna_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+                owner: Addr::unchecked("owner"),
+                native_swap_mode: NativeSwapMode::Disabled,
+                fee_rate: Decimal::zero(),
+                fee_collector: None,
+                guardian: None,
+                paused: false,
+                min_price_update_interval: 0,
+                rate_jump_threshold: Decimal::zero(),
+                circuit_breaker_threshold: Decimal::zero(),
+                circuit_breaker_window: 0,
+                circuit_breaker_tripped: false,
+                circuit_breaker_reset_time: 0,
+                reserve_model: ReserveModel::ExchangeRate,
+            },
+            &hub,
+        )
+        .unwrap();
+
+        assert_eq!(result.is_some, config.is_some());
+
+        if let Some(config) = config {
+            assert_eq!(config.block_time, result.block_time_last);
+            assert_eq!(
+                config.price0_cumulative / Uint128::from(price_precision),
+                Uint128::new(result.price_x)
+            );
+            assert_eq!(
+                config.price1_cumulative / Uint128::from(price_precision),
                 Uint128::new(result.price_y)
             );
         }
     }
 }
 
+#[test]
+fn test_accumulate_prices_modes() {
+    let mut deps = dependencies(&[]);
+
+    // the stLuna price was 1 since 1000 until the Hub changed it to 1.5 at 1800
+    deps.querier.with_hub_last_index_modification(1800);
+    let mut config = Config {
+        block_time_last: 1000,
+        price0_cumulative_last: Uint128::zero(),
+        price1_cumulative_last: Uint128::zero(),
+        price0_last: Uint128::new(1_000_000),
+        price1_last: Uint128::new(1_000_000),
+        accumulator_mode: AccumulatorMode::BlockTime,
+        hub_addr: Addr::unchecked(MOCK_HUB_CONTRACT_ADDR),
+        stluna_addr: Addr::unchecked(MOCK_STLUNA_TOKEN_CONTRACT_ADDR),
+        bluna_addr: Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+        owner: Addr::unchecked("owner"),
+        native_swap_mode: NativeSwapMode::Disabled,
+        fee_rate: Decimal::zero(),
+        fee_collector: None,
+        guardian: None,
+        paused: false,
+        min_price_update_interval: 0,
+        rate_jump_threshold: Decimal::zero(),
+        circuit_breaker_threshold: Decimal::zero(),
+        circuit_breaker_window: 0,
+        circuit_breaker_tripped: false,
+        circuit_breaker_reset_time: 0,
+        reserve_model: ReserveModel::ExchangeRate,
+    };
+
+    // the block time mode applies the new price to the whole period since the last accumulation
+    let block_time_mode = accumulate_prices(
+        mock_env_with_block_time(2000),
+        &config,
+        &hub_snapshot(deps.as_ref()),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(block_time_mode.price0, Uint128::new(1_500_000));
+    assert_eq!(
+        block_time_mode.price0_cumulative,
+        Uint128::new(1000 * 1_500_000)
+    );
+    assert_eq!(
+        block_time_mode.price1_cumulative,
+        Uint128::from(1000u128) * block_time_mode.price1
+    );
+
+    // the Hub mode applies the new price since the Hub's update only
+    config.accumulator_mode = AccumulatorMode::HubIndexModification;
+    let hub_mode = accumulate_prices(
+        mock_env_with_block_time(2000),
+        &config,
+        &hub_snapshot(deps.as_ref()),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(hub_mode.block_time, 2000);
+    assert_eq!(hub_mode.price0, block_time_mode.price0);
+    assert_eq!(
+        hub_mode.price0_cumulative,
+        Uint128::new(800 * 1_000_000 + 200 * 1_500_000)
+    );
+    assert_eq!(
+        hub_mode.price1_cumulative,
+        Uint128::new(800 * 1_000_000) + Uint128::from(200u128) * hub_mode.price1
+    );
+
+    // the modes are the same if the Hub's update is older than the last accumulation
+    deps.querier.with_hub_last_index_modification(500);
+    assert_eq!(
+        accumulate_prices(
+            mock_env_with_block_time(2000),
+            &config,
+            &hub_snapshot(deps.as_ref())
+        )
+        .unwrap(),
+        Some(block_time_mode.clone())
+    );
+
+    // or if the previous prices are unknown
+    deps.querier.with_hub_last_index_modification(1800);
+    config.price0_last = Uint128::zero();
+    assert_eq!(
+        accumulate_prices(
+            mock_env_with_block_time(2000),
+            &config,
+            &hub_snapshot(deps.as_ref())
+        )
+        .unwrap(),
+        Some(block_time_mode)
+    );
+}
+
+#[test]
+fn update_prices() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    // anyone can update the prices
+    let res = execute(
+        deps.as_mut(),
+        mock_env_with_block_time(1000),
+        mock_info("keeper", &[]),
+        ExecuteMsg::UpdatePrices {},
+    )
+    .unwrap();
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.block_time_last, 1000);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_prices"),
+            attr("price0_cumulative_last", config.price0_cumulative_last),
+            attr("price1_cumulative_last", config.price1_cumulative_last),
+            attr("block_time_last", "1000"),
+        ]
+    );
+
+    let msg = update_config_msg(UpdateConfigParams {
+        min_price_update_interval: Some(600),
+        ..Default::default()
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_config"),
+            attr("min_price_update_interval", "600"),
+        ]
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env_with_block_time(1500),
+        mock_info("keeper", &[]),
+        ExecuteMsg::UpdatePrices {},
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PriceUpdateTooEarly {
+            next_update_time: 1600
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env_with_block_time(1600),
+        mock_info("keeper", &[]),
+        ExecuteMsg::UpdatePrices {},
+    )
+    .unwrap();
+    assert_eq!(
+        CONFIG.load(deps.as_ref().storage).unwrap().block_time_last,
+        1600
+    );
+
+    // both updates are observed
+    let res = query_twap(deps.as_ref(), mock_env_with_block_time(1600), 600).unwrap();
+    assert_eq!(res.stluna_price, Decimal::from_str("1.5").unwrap());
+}
+
+#[test]
+fn circuit_breaker() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let mut update_config = |circuit_breaker_threshold, circuit_breaker_window| {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("owner", &[]),
+            update_config_msg(UpdateConfigParams {
+                guardian: Some("guardian".to_string()),
+                circuit_breaker_threshold,
+                circuit_breaker_window,
+                ..Default::default()
+            }),
+        )
+    };
+    let err = update_config(Some(Decimal::percent(10)), None).unwrap_err();
+    assert_eq!(err, ContractError::CircuitBreakerWindowNotSet {});
+    let err =
+        update_config(Some(Decimal::percent(10)), Some(OBSERVATIONS_COVERAGE + 1)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CircuitBreakerWindowTooLong {
+            max_window: OBSERVATIONS_COVERAGE
+        }
+    );
+    update_config(Some(Decimal::percent(10)), Some(1000)).unwrap();
+
+    let update_prices = |deps: &mut OwnedDeps<_, _, _>, time| {
+        execute(
+            deps.as_mut(),
+            mock_env_with_block_time(time),
+            mock_info("keeper", &[]),
+            ExecuteMsg::UpdatePrices {},
+        )
+        .unwrap()
+    };
+    let swap = |deps: &mut OwnedDeps<_, _, _>, time| {
+        let msg = Receive(Cw20ReceiveMsg {
+            sender: "addr".to_string(),
+            amount: Uint128::from(100u128),
+            msg: to_binary(&Cw20HookMsg::Swap {
+                belief_price: None,
+                max_spread: None,
+                to: None,
+                minimum_receive: None,
+            })
+            .unwrap(),
+        });
+        execute(
+            deps.as_mut(),
+            mock_env_with_block_time(time),
+            mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]),
+            msg,
+        )
+    };
+
+    // the TWAP is collected at the stLuna exchange rate 1.5
+    update_prices(&mut deps, 1000);
+    update_prices(&mut deps, 2000);
+    swap(&mut deps, 2100).unwrap();
+
+    // the Hub's rate deviates from the TWAP
+    deps.querier.with_hub_exchange_rates(
+        Decimal::from_str("2").unwrap(),
+        Decimal::from_str("0.95").unwrap(),
+    );
+    let err = swap(&mut deps, 2100).unwrap_err();
+    assert_eq!(err, ContractError::CircuitBreakerTripped {});
+
+    // the keeper trips the circuit breaker
+    let res = update_prices(&mut deps, 2200);
+    assert_eq!(
+        res.attributes.last(),
+        Some(&attr("circuit_breaker_tripped", "true"))
+    );
+    assert!(query_config(deps.as_ref()).unwrap().circuit_breaker_tripped);
+
+    // swaps are halted even if the rate is back
+    deps.querier.with_hub_exchange_rates(
+        Decimal::from_str("1.5").unwrap(),
+        Decimal::from_str("0.95").unwrap(),
+    );
+    let err = swap(&mut deps, 2300).unwrap_err();
+    assert_eq!(err, ContractError::CircuitBreakerTripped {});
+
+    // only the guardian can reset it
+    let err = execute(
+        deps.as_mut(),
+        mock_env_with_block_time(2300),
+        mock_info("owner", &[]),
+        ExecuteMsg::ResetCircuitBreaker {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let res = execute(
+        deps.as_mut(),
+        mock_env_with_block_time(2300),
+        mock_info("guardian", &[]),
+        ExecuteMsg::ResetCircuitBreaker {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "reset_circuit_breaker")]
+    );
+    assert!(!query_config(deps.as_ref()).unwrap().circuit_breaker_tripped);
+
+    // the new rate is accepted until the whole window passes after the reset
+    deps.querier.with_hub_exchange_rates(
+        Decimal::from_str("2").unwrap(),
+        Decimal::from_str("0.95").unwrap(),
+    );
+    swap(&mut deps, 2400).unwrap();
+}
+
+#[test]
+fn circuit_breaker_under_high_frequency_updates() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let msg = update_config_msg(UpdateConfigParams {
+        circuit_breaker_threshold: Some(Decimal::percent(10)),
+        circuit_breaker_window: Some(3600),
+        ..Default::default()
+    });
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+    // the prices are accumulated every 5 seconds, 720 times within the window
+    for time in (1000..=6000).step_by(5) {
+        execute(
+            deps.as_mut(),
+            mock_env_with_block_time(time),
+            mock_info("keeper", &[]),
+            ExecuteMsg::UpdatePrices {},
+        )
+        .unwrap();
+    }
+
+    deps.querier.with_hub_exchange_rates(
+        Decimal::from_str("2").unwrap(),
+        Decimal::from_str("0.95").unwrap(),
+    );
+    let msg = Receive(Cw20ReceiveMsg {
+        sender: "addr".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::Swap {
+            belief_price: None,
+            max_spread: None,
+            to: None,
+            minimum_receive: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env_with_block_time(6005),
+        mock_info(MOCK_STLUNA_TOKEN_CONTRACT_ADDR, &[]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::CircuitBreakerTripped {});
+}
+
+#[test]
+fn rate_history() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let msg = update_config_msg(UpdateConfigParams {
+        rate_jump_threshold: Some(Decimal::percent(10)),
+        ..Default::default()
+    });
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+    let update_prices = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>,
+                         height: u64,
+                         bluna_exchange_rate: &str| {
+        deps.querier.with_hub_exchange_rates(
+            Decimal::from_str("1.5").unwrap(),
+            Decimal::from_str(bluna_exchange_rate).unwrap(),
+        );
+        let mut env = mock_env_with_block_time(height * 10);
+        env.block.height = height;
+        execute(
+            deps.as_mut(),
+            env,
+            mock_info("keeper", &[]),
+            ExecuteMsg::UpdatePrices {},
+        )
+        .unwrap()
+    };
+
+    // the first snapshot and small changes do not emit the event
+    assert!(update_prices(&mut deps, 100, "0.95").events.is_empty());
+    assert!(update_prices(&mut deps, 101, "0.9").events.is_empty());
+
+    // slashing
+    let res = update_prices(&mut deps, 102, "0.8");
+    assert_eq!(
+        res.events,
+        vec![Event::new("rate_jump").add_attributes(vec![
+            attr("previous_height", "101"),
+            attr("previous_stluna_exchange_rate", "1.5"),
+            attr("previous_bluna_exchange_rate", "0.9"),
+            attr("stluna_exchange_rate", "1.5"),
+            attr("bluna_exchange_rate", "0.8"),
+        ])]
+    );
+
+    let res: RateHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RateHistory {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.snapshots
+            .iter()
+            .map(|snapshot| (snapshot.height, snapshot.timestamp))
+            .collect::<Vec<_>>(),
+        vec![(102, 1020), (101, 1010), (100, 1000)]
+    );
+    assert_eq!(
+        res.snapshots[0].bluna_exchange_rate,
+        Decimal::from_str("0.8").unwrap()
+    );
+
+    let res = query_rate_history(deps.as_ref(), Some(102), Some(1)).unwrap();
+    assert_eq!(res.snapshots.len(), 1);
+    assert_eq!(res.snapshots[0].height, 101);
+
+    // the snapshots older than the retention period are pruned
+    update_prices(&mut deps, 101 + RATE_HISTORY_RETENTION, "0.8");
+    let res = query_rate_history(deps.as_ref(), None, None).unwrap();
+    assert_eq!(
+        res.snapshots
+            .iter()
+            .map(|snapshot| snapshot.height)
+            .collect::<Vec<_>>(),
+        vec![101 + RATE_HISTORY_RETENTION, 102, 101]
+    );
+
+    update_prices(&mut deps, 200 + RATE_HISTORY_RETENTION, "0.8");
+    let res = query_rate_history(deps.as_ref(), None, None).unwrap();
+    assert_eq!(
+        res.snapshots
+            .iter()
+            .map(|snapshot| snapshot.height)
+            .collect::<Vec<_>>(),
+        vec![200 + RATE_HISTORY_RETENTION, 101 + RATE_HISTORY_RETENTION]
+    );
+}
+
+#[test]
+fn rate_history_pruning_is_capped() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let update_prices = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>,
+                         height: u64| {
+        let mut env = mock_env_with_block_time(height * 10);
+        env.block.height = height;
+        execute(
+            deps.as_mut(),
+            env,
+            mock_info("keeper", &[]),
+            ExecuteMsg::UpdatePrices {},
+        )
+        .unwrap();
+    };
+    let snapshot_heights = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| {
+        query_rate_history(deps.as_ref(), None, Some(30))
+            .unwrap()
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.height)
+            .collect::<Vec<_>>()
+    };
+
+    let filled = 2 * RATE_HISTORY_PRUNE_LIMIT + 5;
+    for height in 1..=filled {
+        update_prices(&mut deps, height);
+    }
+    assert_eq!(snapshot_heights(&deps).len() as u64, filled);
+
+    // all the filled snapshots are expired, but only the oldest RATE_HISTORY_PRUNE_LIMIT are pruned per update
+    let height = filled + RATE_HISTORY_RETENTION + 1;
+    update_prices(&mut deps, height);
+    let mut expected = vec![height];
+    expected.extend((RATE_HISTORY_PRUNE_LIMIT + 1..=filled).rev());
+    assert_eq!(snapshot_heights(&deps), expected);
+
+    update_prices(&mut deps, height + 1);
+    let mut expected = vec![height + 1, height];
+    expected.extend((2 * RATE_HISTORY_PRUNE_LIMIT + 1..=filled).rev());
+    assert_eq!(snapshot_heights(&deps), expected);
+
+    update_prices(&mut deps, height + 2);
+    assert_eq!(
+        snapshot_heights(&deps),
+        vec![height + 2, height + 1, height]
+    );
+}
+
+#[test]
+fn twap_query() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    // the stLuna price was 1 between 1000 and 2000, the bLuna price was 0.5
+    for (timestamp, price0_cumulative, price1_cumulative) in
+        [(1000, 0u128, 0u128), (2000, 1_000_000_000, 500_000_000)]
+    {
+        store_observation(
+            deps.as_mut().storage,
+            &Observation {
+                timestamp,
+                price0_cumulative: Uint128::new(price0_cumulative),
+                price1_cumulative: Uint128::new(price1_cumulative),
+            },
+        )
+        .unwrap();
+    }
+    let mut config = CONFIG.load(deps.as_ref().storage).unwrap();
+    config.block_time_last = 2000;
+    config.price0_cumulative_last = Uint128::new(1_000_000_000);
+    config.price1_cumulative_last = Uint128::new(500_000_000);
+    CONFIG.save(deps.as_mut().storage, &config).unwrap();
+
+    // the Hub's stLuna price is 1.5 since 2000
+    let env = mock_env_with_block_time(3000);
+    for (window_seconds, stluna_price) in [
+        (500, "1.5"),
+        (1000, "1.5"),
+        (1500, "1.333333333333333333"),
+        (2000, "1.25"),
+    ] {
+        let res: TwapResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::Twap { window_seconds },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.window_seconds, window_seconds);
+        assert_eq!(res.stluna_price, Decimal::from_str(stluna_price).unwrap());
+    }
+
+    let recent = query_twap(deps.as_ref(), env.clone(), 500).unwrap();
+    let last_period = query_twap(deps.as_ref(), env.clone(), 1000).unwrap();
+    assert_eq!(recent.bluna_price, last_period.bluna_price);
+    let whole_period = query_twap(deps.as_ref(), env.clone(), 2000).unwrap();
+    assert!(whole_period.bluna_price < last_period.bluna_price);
+
+    let err = query_twap(deps.as_ref(), env.clone(), 2001).unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err("No price observations cover the window of 2001 seconds")
+    );
+    let err = query_twap(deps.as_ref(), env, 0).unwrap_err();
+    assert_eq!(err, StdError::generic_err("window must be positive"));
+
+    // the oldest observation is overwritten when the ring buffer is full
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    let last = (OBSERVATIONS_CAPACITY + 1) * OBSERVATION_INTERVAL;
+    for timestamp in (1..=OBSERVATIONS_CAPACITY + 1).map(|i| i * OBSERVATION_INTERVAL) {
+        store_observation(
+            deps.as_mut().storage,
+            &Observation {
+                timestamp,
+                price0_cumulative: Uint128::from(timestamp * 1_000_000),
+                price1_cumulative: Uint128::from(timestamp * 1_000_000),
+            },
+        )
+        .unwrap();
+    }
+    let mut config = CONFIG.load(deps.as_ref().storage).unwrap();
+    config.block_time_last = last;
+    config.price0_cumulative_last = Uint128::from(last * 1_000_000);
+    config.price1_cumulative_last = Uint128::from(last * 1_000_000);
+    CONFIG.save(deps.as_mut().storage, &config).unwrap();
+
+    let env = mock_env_with_block_time(last);
+    let window = (OBSERVATIONS_CAPACITY - 1) * OBSERVATION_INTERVAL;
+    let res = query_twap(deps.as_ref(), env.clone(), window).unwrap();
+    assert_eq!(res.stluna_price, Decimal::one());
+    assert_eq!(res.bluna_price, Decimal::one());
+    query_twap(deps.as_ref(), env, window + 1).unwrap_err();
+}
+
+#[test]
+fn twap_query_under_high_frequency_updates() {
+    let mut deps = dependencies(&[]);
+
+    initialize(deps.borrow_mut());
+
+    // the prices are accumulated every 5 seconds for two days: stLuna at 1.5, bLuna at 0.5
+    let last = 2 * 86400;
+    for timestamp in (0..=last).step_by(5) {
+        store_observation(
+            deps.as_mut().storage,
+            &Observation {
+                timestamp,
+                price0_cumulative: Uint128::from(timestamp * 1_500_000),
+                price1_cumulative: Uint128::from(timestamp * 500_000),
+            },
+        )
+        .unwrap();
+    }
+    let count = OBSERVATIONS_COUNT.load(deps.as_ref().storage).unwrap();
+    assert_eq!(count, last / OBSERVATION_INTERVAL + 1);
+
+    let mut config = CONFIG.load(deps.as_ref().storage).unwrap();
+    config.block_time_last = last;
+    config.price0_cumulative_last = Uint128::from(last * 1_500_000);
+    config.price1_cumulative_last = Uint128::from(last * 500_000);
+    CONFIG.save(deps.as_mut().storage, &config).unwrap();
+
+    // a day-long window is still covered by the ring buffer
+    let env = mock_env_with_block_time(last);
+    for window_seconds in [3600, 86400] {
+        let res = query_twap(deps.as_ref(), env.clone(), window_seconds).unwrap();
+        assert_eq!(res.stluna_price, Decimal::from_str("1.5").unwrap());
+        assert_eq!(res.bluna_price, Decimal::from_str("0.5").unwrap());
+    }
+}
+
+fn update_config_msg(params: UpdateConfigParams) -> ExecuteMsg {
+    let UpdateConfigParams {
+        hub_address,
+        stluna_address,
+        bluna_address,
+        native_swap_mode,
+        fee_rate,
+        fee_collector,
+        guardian,
+        min_price_update_interval,
+        accumulator_mode,
+        rate_jump_threshold,
+        circuit_breaker_threshold,
+        circuit_breaker_window,
+        reserve_model,
+    } = params;
+    ExecuteMsg::UpdateConfig {
+        hub_address,
+        stluna_address,
+        bluna_address,
+        native_swap_mode,
+        fee_rate,
+        fee_collector,
+        guardian,
+        min_price_update_interval,
+        accumulator_mode,
+        rate_jump_threshold,
+        circuit_breaker_threshold,
+        circuit_breaker_window,
+        reserve_model,
+    }
+}
+
+fn hub_snapshot(deps: Deps) -> HubSnapshot {
+    query_hub_snapshot(
+        deps,
+        Addr::unchecked(MOCK_HUB_CONTRACT_ADDR),
+        Addr::unchecked(MOCK_BLUNA_TOKEN_CONTRACT_ADDR),
+    )
+    .unwrap()
+}
+
 fn mock_env_with_block_time(time: u64) -> Env {
     let mut env = mock_env();
     env.block = BlockInfo {